@@ -1,3 +0,0 @@
-pub type Square = u8;
-pub type Move = u16;
-pub type Bitboard = u64;
@@ -0,0 +1,77 @@
+use crate::tables::SliderAttacks;
+use crate::types::{Bitboard, Square};
+use crate::{enums, masks, tables};
+
+use std::sync::OnceLock;
+
+// Process-wide attack tables, built on first use and shared by every thread
+static MASKS: OnceLock<masks::Lookup> = OnceLock::new();
+static SLIDERS: OnceLock<tables::Lookup> = OnceLock::new();
+
+pub fn masks() -> &'static masks::Lookup {
+    MASKS.get_or_init(masks::Lookup::new)
+}
+
+pub fn sliders() -> &'static tables::Lookup {
+    SLIDERS.get_or_init(|| tables::Lookup::new(masks()))
+}
+
+// bishop_attacks returns the squares a bishop on sq attacks, blocked by occ
+pub fn bishop_attacks(sq: Square, occ: Bitboard) -> Bitboard {
+    sliders().bishop_attacks(masks(), sq, occ)
+}
+
+// rook_attacks returns the squares a rook on sq attacks, blocked by occ
+pub fn rook_attacks(sq: Square, occ: Bitboard) -> Bitboard {
+    sliders().rook_attacks(masks(), sq, occ)
+}
+
+// queen_attacks returns the squares a queen on sq attacks, blocked by occ
+pub fn queen_attacks(sq: Square, occ: Bitboard) -> Bitboard {
+    bishop_attacks(sq, occ) | rook_attacks(sq, occ)
+}
+
+pub fn knight_attacks(sq: Square) -> Bitboard {
+    masks().knight[sq.index()]
+}
+
+pub fn king_attacks(sq: Square) -> Bitboard {
+    masks().king[sq.index()]
+}
+
+// pawn_attacks returns the squares a pawn of the given colour on sq captures on
+pub fn pawn_attacks(colour: enums::Colour, sq: Square) -> Bitboard {
+    masks().pcapture[colour as usize][sq.index()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magic;
+
+    fn bb(squares: &[&str]) -> Bitboard {
+        squares
+            .iter()
+            .map(|s| s.parse::<Square>().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn attacks_from_squares() {
+        let d4 = Square::D4;
+        let occ = bb(&["d6", "f4", "b2"]);
+        assert_eq!(bishop_attacks(d4, occ), magic::batk(d4, occ));
+        assert_eq!(rook_attacks(d4, occ), magic::ratk(d4, occ));
+        assert_eq!(
+            queen_attacks(d4, occ),
+            magic::batk(d4, occ) | magic::ratk(d4, occ)
+        );
+        assert_eq!(knight_attacks(Square::A1), bb(&["b3", "c2"]));
+        assert_eq!(king_attacks(Square::H8), bb(&["g8", "g7", "h7"]));
+        assert_eq!(
+            pawn_attacks(enums::Colour::White, Square::E4),
+            bb(&["d5", "f5"])
+        );
+        assert_eq!(pawn_attacks(enums::Colour::Black, Square::A7), bb(&["b6"]));
+    }
+}
@@ -0,0 +1,200 @@
+use crate::positions::Position;
+use crate::tables::SliderAttacks;
+use crate::types::{Bitboard, Square};
+use crate::{magic, magic_numbers, masks, sliders, tables, uci};
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+// bench times parts of the engine, printing one line per measurement.
+//
+// ragfish bench tables [--magics PATH]
+// ragfish bench sliders
+// ragfish bench movegen
+// ragfish bench perft [DEPTH]
+const USAGE: &str =
+    "usage: ragfish bench tables [--magics PATH] | sliders | movegen | perft [DEPTH]";
+
+// lookups timed per measurement
+const LOOKUPS: usize = 1 << 24;
+
+pub fn run(args: &[String]) {
+    let result = match args.first().map(String::as_str) {
+        Some("tables") => bench_tables(&args[1..]),
+        Some("sliders") => {
+            bench_sliders();
+            Ok(())
+        }
+        Some("movegen") => {
+            bench_movegen();
+            Ok(())
+        }
+        Some("perft") => bench_perft(&args[1..]),
+        _ => Err(String::from("expected a benchmark")),
+    };
+    if let Err(e) = result {
+        eprintln!("{}\n{}", e, USAGE);
+        std::process::exit(2);
+    }
+}
+
+// bench_tables reports the size of the slider attack tables and the cost of a
+// lookup in each magic layout, for the shipped magics or ones saved by
+// magic-search, and for PEXT when the CPU has it
+fn bench_tables(args: &[String]) -> Result<(), String> {
+    let (bmag, rmag) = match args {
+        [] => (magic_numbers::BISHOP, magic_numbers::ROOK),
+        [flag, path] if flag == "--magics" => {
+            let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+            magic::from_bytes(&bytes).ok_or(format!("{} is not a magics file", path))?
+        }
+        _ => return Err(String::from("bad arguments")),
+    };
+
+    let ms = masks::Lookup::new();
+    let queries = queries(4096);
+    let mut sizes = Vec::new();
+    for layout in [tables::Layout::Dense, tables::Layout::Packed] {
+        let start = Instant::now();
+        let t = tables::Magics::from_magics(&ms, &bmag, &rmag, layout)?;
+        report(&format!("{:?}", layout), &ms, &t, start, &queries);
+        sizes.push(t.memory());
+    }
+    if sizes[0] == sizes[1] {
+        println!("Packed: these magics leave no slots to share, no smaller than Dense");
+    }
+    #[cfg(target_arch = "x86_64")]
+    if crate::pext::is_available() {
+        let start = Instant::now();
+        let t = crate::pext::Lookup::new(&ms);
+        report("Pext", &ms, &t, start, &queries);
+    }
+    Ok(())
+}
+
+// bench_sliders compares the slider attack backends
+fn bench_sliders() {
+    let ms = masks::Lookup::new();
+    let queries = queries(4096);
+    let start = Instant::now();
+    report("Magics", &ms, &tables::Magics::new(&ms), start, &queries);
+    #[cfg(target_arch = "x86_64")]
+    if crate::pext::is_available() {
+        let start = Instant::now();
+        report("Pext", &ms, &crate::pext::Lookup::new(&ms), start, &queries);
+    }
+    let start = Instant::now();
+    report(
+        "Hyperbola",
+        &ms,
+        &sliders::Hyperbola::new(&ms),
+        start,
+        &queries,
+    );
+    let start = Instant::now();
+    report(
+        "Classical",
+        &ms,
+        &sliders::Classical::new(&ms),
+        start,
+        &queries,
+    );
+    let start = Instant::now();
+    report(
+        "Kindergarten",
+        &ms,
+        &sliders::Kindergarten::new(&ms),
+        start,
+        &queries,
+    );
+}
+
+// Positions with a spread of piece counts and move types, from
+// https://www.chessprogramming.org/Perft_Results
+const POSITIONS: [&str; 4] = [
+    uci::STARTPOS,
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+];
+
+// bench_movegen reports the cost of generating the legal moves of a position
+fn bench_movegen() {
+    let positions: Vec<Position> = POSITIONS.iter().map(|fen| Position::new(fen)).collect();
+    let (mut calls, mut moves) = (0u64, 0u64);
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(1) {
+        for pos in &positions {
+            moves += black_box(pos).generate_legal().len() as u64;
+            calls += 1;
+        }
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "movegen: {:.0} ns per position, {:.1} M moves/s",
+        elapsed.as_nanos() as f64 / calls as f64,
+        moves as f64 / elapsed.as_secs_f64() / 1e6
+    );
+}
+
+// bench_perft reports the speed of perft from the start position, at depth 5
+// unless told otherwise
+fn bench_perft(args: &[String]) -> Result<(), String> {
+    let depth = match args {
+        [] => 5,
+        [depth] => depth
+            .parse::<u8>()
+            .map_err(|_| format!("bad depth {}", depth))?,
+        _ => return Err(String::from("bad arguments")),
+    };
+    let start = Instant::now();
+    let nodes = Position::new(uci::STARTPOS).perft(depth);
+    let elapsed = start.elapsed();
+    println!(
+        "perft {}: {} nodes in {} ms, {:.1} M nodes/s",
+        depth,
+        nodes,
+        elapsed.as_millis(),
+        nodes as f64 / elapsed.as_secs_f64() / 1e6
+    );
+    Ok(())
+}
+
+// report prints the size of t, the time since start spent building it and
+// the cost of a lookup
+fn report(
+    name: &str,
+    ms: &masks::Lookup,
+    t: &impl SliderAttacks,
+    start: Instant,
+    queries: &[(Square, Bitboard)],
+) {
+    let built = start.elapsed();
+    let start = Instant::now();
+    let mut acc = Bitboard::EMPTY;
+    for &(sq, occ) in queries.iter().cycle().take(LOOKUPS / 2) {
+        acc ^= t.bishop_attacks(ms, black_box(sq), black_box(occ));
+        acc ^= t.rook_attacks(ms, black_box(sq), black_box(occ));
+    }
+    black_box(acc);
+    let ns = start.elapsed().as_nanos() as f64 / LOOKUPS as f64;
+
+    println!(
+        "{}: {} KiB, built in {} ms, {:.2} ns per lookup",
+        name,
+        t.memory() / 1024,
+        built.as_millis(),
+        ns
+    );
+}
+
+// queries gives random squares with occupancies of about a quarter of the board
+fn queries(n: usize) -> Vec<(Square, Bitboard)> {
+    let mut rng = magic::Prng::new(0x5eedf15b);
+    (0..n)
+        .map(|_| {
+            let sq = Square::new((rng.next() % 64) as u8);
+            (sq, Bitboard(rng.next() & rng.next()))
+        })
+        .collect()
+}
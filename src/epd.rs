@@ -0,0 +1,255 @@
+use crate::positions::{FenError, Position};
+use crate::san::{self, SanError};
+use crate::types::Move;
+
+// Extended Position Description: the first four fields of a FEN followed by
+// operations, each an opcode and its operands ending with a semicolon, as in
+// 2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001";
+// See: https://www.chessprogramming.org/Extended_Position_Description
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EpdError {
+    Fen(FenError),
+    // An unterminated string or an operation without an opcode
+    Syntax,
+    Move {
+        opcode: String,
+        text: String,
+        error: SanError,
+    },
+    // A numeric operand which is missing or not a number
+    Operand {
+        opcode: String,
+    },
+}
+
+impl std::fmt::Display for EpdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EpdError::Fen(e) => write!(f, "{}", e),
+            EpdError::Syntax => write!(f, "bad operation"),
+            EpdError::Move {
+                opcode,
+                text,
+                error,
+            } => write!(f, "{} {}: {}", opcode, text, error),
+            EpdError::Operand { opcode } => write!(f, "{} needs a number", opcode),
+        }
+    }
+}
+
+impl std::error::Error for EpdError {}
+
+pub struct Epd {
+    // the counters come from the hmvc and fmvn operations when present
+    pub position: Position,
+    pub id: Option<String>,
+    // c0, the first of the comments
+    pub comment: Option<String>,
+    // bm and am, the moves to find and the moves to avoid
+    pub best_moves: Vec<Move>,
+    pub avoid_moves: Vec<Move>,
+    // dm, the moves to a direct mate
+    pub mate: Option<u32>,
+    // acd, the depth the position was analysed to
+    pub depth: Option<u8>,
+    // every operation in the order given, including the ones above
+    pub operations: Vec<(String, Vec<String>)>,
+}
+
+impl Epd {
+    pub fn parse(line: &str) -> Result<Epd, EpdError> {
+        let mut rest = line.trim_start();
+        let mut fields = Vec::new();
+        for _ in 0..4 {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            fields.push(&rest[..end]);
+            rest = rest[end..].trim_start();
+        }
+        let operations = operations(rest)?;
+
+        let operand = |opcode: &str| {
+            operations
+                .iter()
+                .find(|(op, _)| op == opcode)
+                .and_then(|(_, operands)| operands.first())
+        };
+        let mut fen = fields.join(" ").trim_end().to_string();
+        if !fields.contains(&"") {
+            fen.push_str(&format!(
+                " {} {}",
+                operand("hmvc").map_or("0", String::as_str),
+                operand("fmvn").map_or("1", String::as_str)
+            ));
+        }
+        let position = Position::from_fen(&fen).map_err(EpdError::Fen)?;
+
+        let moves = |opcode: &str, operands: &[String]| {
+            operands
+                .iter()
+                .map(|text| {
+                    san::parse(&position, text).map_err(|error| EpdError::Move {
+                        opcode: opcode.to_string(),
+                        text: text.clone(),
+                        error,
+                    })
+                })
+                .collect::<Result<Vec<Move>, EpdError>>()
+        };
+        let number = |opcode: &str, operands: &[String]| {
+            operands
+                .first()
+                .and_then(|n| n.parse::<u32>().ok())
+                .ok_or(EpdError::Operand {
+                    opcode: opcode.to_string(),
+                })
+        };
+
+        let mut ret = Epd {
+            position,
+            id: None,
+            comment: None,
+            best_moves: Vec::new(),
+            avoid_moves: Vec::new(),
+            mate: None,
+            depth: None,
+            operations: Vec::new(),
+        };
+        for (opcode, operands) in &operations {
+            match opcode.as_str() {
+                "bm" => ret.best_moves = moves(opcode, operands)?,
+                "am" => ret.avoid_moves = moves(opcode, operands)?,
+                "id" => ret.id = operands.first().cloned(),
+                "c0" => ret.comment = operands.first().cloned(),
+                "dm" => ret.mate = Some(number(opcode, operands)?),
+                "acd" => ret.depth = Some(number(opcode, operands)?.min(u8::MAX as u32) as u8),
+                _ => {}
+            }
+        }
+        ret.operations = operations;
+        Ok(ret)
+    }
+}
+
+// operations splits the text after the position into opcodes and operands,
+// taking the quotes off strings. The last semicolon may be left out.
+fn operations(text: &str) -> Result<Vec<(String, Vec<String>)>, EpdError> {
+    let mut ret = Vec::new();
+    let mut tokens: Vec<String> = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            ';' => {
+                if tokens.is_empty() {
+                    return Err(EpdError::Syntax);
+                }
+                let opcode = tokens.remove(0);
+                ret.push((opcode, std::mem::take(&mut tokens)));
+            }
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => s.push(c),
+                        None => return Err(EpdError::Syntax),
+                    }
+                }
+                tokens.push(s);
+            }
+            c => {
+                let mut s = String::from(c);
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == ';' {
+                        break;
+                    }
+                    s.push(c);
+                    chars.next();
+                }
+                tokens.push(s);
+            }
+        }
+    }
+    if !tokens.is_empty() {
+        let opcode = tokens.remove(0);
+        ret.push((opcode, tokens));
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils;
+
+    #[test]
+    fn parses_operations() {
+        let epd = Epd::parse(
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\";",
+        )
+        .unwrap();
+        assert_eq!(epd.id.as_deref(), Some("WAC.001"));
+        let best: Vec<String> = epd
+            .best_moves
+            .iter()
+            .map(|&mv| utils::move_string(mv))
+            .collect();
+        assert_eq!(best, ["g3g6"]);
+        assert_eq!(
+            epd.position.to_fen(),
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1"
+        );
+
+        let epd = Epd::parse(
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - \
+             am Ng5 Ng1; bm Bb5 Bc4 d4; c0 \"book; main lines\"; acd 12; dm 0; \
+             hmvc 2; fmvn 3; xyz 1 2",
+        )
+        .unwrap();
+        assert_eq!(epd.avoid_moves.len(), 2);
+        assert_eq!(epd.best_moves.len(), 3);
+        assert_eq!(epd.comment.as_deref(), Some("book; main lines"));
+        assert_eq!((epd.depth, epd.mate, epd.id), (Some(12), Some(0), None));
+        assert_eq!(
+            epd.operations.last(),
+            Some(&(
+                String::from("xyz"),
+                vec![String::from("1"), String::from("2")]
+            ))
+        );
+        assert_eq!(
+            epd.position.to_fen(),
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3"
+        );
+
+        let epd = Epd::parse("8/8/4k3/8/8/4K3/8/8 b - -").unwrap();
+        assert!(epd.operations.is_empty() && epd.best_moves.is_empty());
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |line: &str| Epd::parse(line).err().map(|e| e.to_string());
+        assert_eq!(
+            error("8/8/4k3/8/8/4K3/8/8 w"),
+            Some(String::from("missing Castling field"))
+        );
+        assert_eq!(
+            error("8/8/4k3/8/8/4K3/8/8 w - - bm Kd5;"),
+            Some(String::from("bm Kd5: no legal move matches"))
+        );
+        assert_eq!(
+            error("8/8/4k3/8/8/4K3/8/8 w - - dm;"),
+            Some(String::from("dm needs a number"))
+        );
+        assert_eq!(
+            error("8/8/4k3/8/8/4K3/8/8 w - - id \"open;"),
+            Some(String::from("bad operation"))
+        );
+        assert_eq!(
+            error("8/8/4k3/8/8/4K3/8/8 w - - ;"),
+            Some(String::from("bad operation"))
+        );
+        assert!(error("8/8/4k3/8/8/4K3/8/8 w - - hmvc x;").is_some());
+    }
+}
@@ -0,0 +1,282 @@
+use crate::enums;
+use crate::positions::Position;
+
+// Tapered evaluation with material and piece-square tables. Values are the
+// PeSTO tables by Ronald Friederich.
+// See: https://www.chessprogramming.org/PeSTO%27s_Evaluation_Function
+//
+// Everything below is indexed by enums::Piece, i.e. knight, bishop, rook,
+// queen, pawn, king.
+
+const MG_VALUE: [i32; 6] = [337, 365, 477, 1025, 82, 0];
+const EG_VALUE: [i32; 6] = [281, 297, 512, 936, 94, 0];
+
+// Contribution of each piece to the game phase, which runs from 24 with all
+// pieces on the board down to 0 with only kings and pawns
+const PHASE: [i32; 6] = [1, 1, 2, 4, 0, 0];
+const MAX_PHASE: i32 = 24;
+
+// The tables are written from white's point of view with rank 8 on the first
+// line, so a white piece on sq reads entry sq.flip(), and a black piece,
+// seeing the board upside down, reads entry sq.
+#[rustfmt::skip]
+const MG_PST: [[i32; 64]; 6] = [
+    // knight
+    [
+        -167, -89, -34, -49,  61, -97, -15, -107,
+         -73, -41,  72,  36,  23,  62,   7,  -17,
+         -47,  60,  37,  65,  84, 129,  73,   44,
+          -9,  17,  19,  53,  37,  69,  18,   22,
+         -13,   4,  16,  13,  28,  19,  21,   -8,
+         -23,  -9,  12,  10,  19,  17,  25,  -16,
+         -29, -53, -12,  -3,  -1,  18, -14,  -19,
+        -105, -21, -58, -33, -17, -28, -19,  -23,
+    ],
+    // bishop
+    [
+        -29,   4, -82, -37, -25, -42,   7,  -8,
+        -26,  16, -18, -13,  30,  59,  18, -47,
+        -16,  37,  43,  40,  35,  50,  37,  -2,
+         -4,   5,  19,  50,  37,  37,   7,  -2,
+         -6,  13,  13,  26,  34,  12,  10,   4,
+          0,  15,  15,  15,  14,  27,  18,  10,
+          4,  15,  16,   0,   7,  21,  33,   1,
+        -33,  -3, -14, -21, -13, -12, -39, -21,
+    ],
+    // rook
+    [
+         32,  42,  32,  51,  63,   9,  31,  43,
+         27,  32,  58,  62,  80,  67,  26,  44,
+         -5,  19,  26,  36,  17,  45,  61,  16,
+        -24, -11,   7,  26,  24,  35,  -8, -20,
+        -36, -26, -12,  -1,   9,  -7,   6, -23,
+        -45, -25, -16, -17,   3,   0,  -5, -33,
+        -44, -16, -20,  -9,  -1,  11,  -6, -71,
+        -19, -13,   1,  17,  16,   7, -37, -26,
+    ],
+    // queen
+    [
+        -28,   0,  29,  12,  59,  44,  43,  45,
+        -24, -39,  -5,   1, -16,  57,  28,  54,
+        -13, -17,   7,   8,  29,  56,  47,  57,
+        -27, -27, -16, -16,  -1,  17,  -2,   1,
+         -9, -26,  -9, -10,  -2,  -4,   3,  -3,
+        -14,   2, -11,  -2,  -5,   2,  14,   5,
+        -35,  -8,  11,   2,   8,  15,  -3,   1,
+         -1, -18,  -9,  10, -15, -25, -31, -50,
+    ],
+    // pawn
+    [
+          0,   0,   0,   0,   0,   0,   0,   0,
+         98, 134,  61,  95,  68, 126,  34, -11,
+         -6,   7,  26,  31,  65,  56,  25, -20,
+        -14,  13,   6,  21,  23,  12,  17, -23,
+        -27,  -2,  -5,  12,  17,   6,  10, -25,
+        -26,  -4,  -4, -10,   3,   3,  33, -12,
+        -35,  -1, -20, -23, -15,  24,  38, -22,
+          0,   0,   0,   0,   0,   0,   0,   0,
+    ],
+    // king
+    [
+        -65,  23,  16, -15, -56, -34,   2,  13,
+         29,  -1, -20,  -7,  -8,  -4, -38, -29,
+         -9,  24,   2, -16, -20,   6,  22, -22,
+        -17, -20, -12, -27, -30, -25, -14, -36,
+        -49,  -1, -27, -39, -46, -44, -33, -51,
+        -14, -14, -22, -46, -44, -30, -15, -27,
+          1,   7,  -8, -64, -43, -16,   9,   8,
+        -15,  36,  12, -54,   8, -28,  24,  14,
+    ],
+];
+
+#[rustfmt::skip]
+const EG_PST: [[i32; 64]; 6] = [
+    // knight
+    [
+        -58, -38, -13, -28, -31, -27, -63, -99,
+        -25,  -8, -25,  -2,  -9, -25, -24, -52,
+        -24, -20,  10,   9,  -1,  -9, -19, -41,
+        -17,   3,  22,  22,  22,  11,   8, -18,
+        -18,  -6,  16,  25,  16,  17,   4, -18,
+        -23,  -3,  -1,  15,  10,  -3, -20, -22,
+        -42, -20, -10,  -5,  -2, -20, -23, -44,
+        -29, -51, -23, -15, -22, -18, -50, -64,
+    ],
+    // bishop
+    [
+        -14, -21, -11,  -8,  -7,  -9, -17, -24,
+         -8,  -4,   7, -12,  -3, -13,  -4, -14,
+          2,  -8,   0,  -1,  -2,   6,   0,   4,
+         -3,   9,  12,   9,  14,  10,   3,   2,
+         -6,   3,  13,  19,   7,  10,  -3,  -9,
+        -12,  -3,   8,  10,  13,   3,  -7, -15,
+        -14, -18,  -7,  -1,   4,  -9, -15, -27,
+        -23,  -9, -23,  -5,  -9, -16,  -5, -17,
+    ],
+    // rook
+    [
+         13,  10,  18,  15,  12,  12,   8,   5,
+         11,  13,  13,  11,  -3,   3,   8,   3,
+          7,   7,   7,   5,   4,  -3,  -5,  -3,
+          4,   3,  13,   1,   2,   1,  -1,   2,
+          3,   5,   8,   4,  -5,  -6,  -8, -11,
+         -4,   0,  -5,  -1,  -7, -12,  -8, -16,
+         -6,  -6,   0,   2,  -9,  -9, -11,  -3,
+         -9,   2,   3,  -1,  -5, -13,   4, -20,
+    ],
+    // queen
+    [
+         -9,  22,  22,  27,  27,  19,  10,  20,
+        -17,  20,  32,  41,  58,  25,  30,   0,
+        -20,   6,   9,  49,  47,  35,  19,   9,
+          3,  22,  24,  45,  57,  40,  57,  36,
+        -18,  28,  19,  47,  31,  34,  39,  23,
+        -16, -27,  15,   6,   9,  17,  10,   5,
+        -22, -23, -30, -16, -16, -23, -36, -32,
+        -33, -28, -22, -43,  -5, -32, -20, -41,
+    ],
+    // pawn
+    [
+          0,   0,   0,   0,   0,   0,   0,   0,
+        178, 173, 158, 134, 147, 132, 165, 187,
+         94, 100,  85,  67,  56,  53,  82,  84,
+         32,  24,  13,   5,  -2,   4,  17,  17,
+         13,   9,  -3,  -7,  -7,  -8,   3,  -1,
+          4,   7,  -6,   1,   0,  -5,  -1,  -8,
+         13,   8,   8,  10,  13,   0,   2,  -7,
+          0,   0,   0,   0,   0,   0,   0,   0,
+    ],
+    // king
+    [
+        -74, -35, -18, -18, -11,  15,   4, -17,
+        -12,  17,  14,  17,  17,  38,  23,  11,
+         10,  17,  23,  15,  20,  45,  44,  13,
+         -8,  22,  24,  27,  26,  33,  26,   3,
+        -18,  -4,  21,  24,  27,  23,   9, -11,
+        -19,  -3,  11,  21,  23,  16,   7,  -9,
+        -27, -11,   4,  13,  14,   4,  -5, -17,
+        -53, -34, -21, -11, -28, -14, -24, -43,
+    ],
+];
+
+// evaluate scores pos in centipawns for the side to move
+pub fn evaluate(pos: &Position) -> i32 {
+    let (mut mg, mut eg, mut phase) = (0, 0, 0);
+    for colour in enums::Colour::values() {
+        let sign = match colour {
+            enums::Colour::White => 1,
+            enums::Colour::Black => -1,
+        };
+        for piece in enums::Piece::values() {
+            let p = piece as usize;
+            for sq in pos.pieces(colour, piece) {
+                let idx = match colour {
+                    enums::Colour::White => sq.flip(),
+                    enums::Colour::Black => sq,
+                }
+                .index();
+                mg += sign * (MG_VALUE[p] + MG_PST[p][idx]);
+                eg += sign * (EG_VALUE[p] + EG_PST[p][idx]);
+                phase += PHASE[p];
+            }
+        }
+    }
+
+    // promotions can push the phase past its maximum
+    let phase = phase.min(MAX_PHASE);
+    let score = (mg * phase + eg * (MAX_PHASE - phase)) / MAX_PHASE;
+    match pos.side() {
+        enums::Colour::White => score,
+        enums::Colour::Black => -score,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Square;
+
+    // flip mirrors a fen top to bottom and swaps the colours of everything
+    fn flip(fen: &str) -> String {
+        let fields: Vec<&str> = fen.split(' ').collect();
+        let swap = |s: &str| -> String {
+            s.chars()
+                .map(|c| {
+                    if c.is_ascii_uppercase() {
+                        c.to_ascii_lowercase()
+                    } else {
+                        c.to_ascii_uppercase()
+                    }
+                })
+                .collect()
+        };
+        let board: Vec<&str> = fields[0].split('/').rev().collect();
+        let side = if fields[1] == "w" { "b" } else { "w" };
+        let mut castling: Vec<char> = swap(fields[2]).chars().collect();
+        castling.sort();
+        let ep = match fields[3].as_bytes() {
+            [fl, b'3'] => format!("{}6", *fl as char),
+            [fl, b'6'] => format!("{}3", *fl as char),
+            _ => String::from("-"),
+        };
+        format!(
+            "{} {} {} {} {} {}",
+            swap(&board.join("/")),
+            side,
+            castling.into_iter().collect::<String>(),
+            ep,
+            fields[4],
+            fields[5]
+        )
+    }
+
+    fn white_score(pos: &Position) -> i32 {
+        match pos.side() {
+            enums::Colour::White => evaluate(pos),
+            enums::Colour::Black => -evaluate(pos),
+        }
+    }
+
+    #[test]
+    fn colour_symmetry() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        ] {
+            let pos = Position::new(fen);
+            let flipped = Position::new(&flip(fen));
+            assert_eq!(white_score(&flipped), -white_score(&pos), "{}", fen);
+            assert_eq!(evaluate(&flipped), evaluate(&pos), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn start_position_is_balanced() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(evaluate(&Position::new(start)), 0);
+    }
+
+    #[test]
+    fn material_counts_for_side_to_move() {
+        let up_a_queen = Position::new("3qk3/8/8/8/8/8/8/4K3 b - - 0 1");
+        assert!(evaluate(&up_a_queen) > 800);
+        let down_a_queen = Position::new("3qk3/8/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(evaluate(&down_a_queen), -evaluate(&up_a_queen));
+    }
+
+    #[test]
+    fn tapers_towards_endgame_values() {
+        // a lone pawn is worth its endgame value plus the square bonus
+        let pos = Position::new("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1");
+        let kings = Position::new("4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        let e2 = Square::E2.flip().index();
+        assert_eq!(
+            evaluate(&pos) - evaluate(&kings),
+            EG_VALUE[enums::Piece::Pawn as usize] + EG_PST[enums::Piece::Pawn as usize][e2]
+        );
+    }
+}
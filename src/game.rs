@@ -0,0 +1,221 @@
+use crate::enums;
+use crate::positions::Position;
+use crate::types::Move;
+
+// A game from some start position, keeping every position reached so that
+// repetitions and the move count rules can be judged
+// See: https://www.chessprogramming.org/Repetitions
+#[derive(Clone)]
+pub struct Game {
+    // the start position, then the position after each move
+    positions: Vec<Position>,
+    moves: Vec<Move>,
+}
+
+// Where a game stands under the rules. Threefold repetition and the fifty-move
+// rule are draws a player may claim, the others end the game by themselves.
+// See: https://handbook.fide.com/chapter/E012023
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum GameStatus {
+    Ongoing,
+    Checkmate { winner: enums::Colour },
+    Stalemate,
+    InsufficientMaterial,
+    ThreefoldRepetition,
+    FivefoldRepetition,
+    FiftyMoveRule,
+    SeventyFiveMoveRule,
+}
+
+impl GameStatus {
+    pub fn is_over(self) -> bool {
+        self != GameStatus::Ongoing
+    }
+
+    pub fn is_draw(self) -> bool {
+        !matches!(self, GameStatus::Ongoing | GameStatus::Checkmate { .. })
+    }
+}
+
+impl Game {
+    pub fn new(start: Position) -> Game {
+        Game {
+            positions: vec![start],
+            moves: Vec::new(),
+        }
+    }
+
+    pub fn start(&self) -> &Position {
+        &self.positions[0]
+    }
+
+    pub fn position(&self) -> &Position {
+        self.positions.last().expect("a game has a start position")
+    }
+
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    // keys returns the Zobrist keys of the positions so far, oldest first
+    pub fn keys(&self) -> Vec<u64> {
+        self.positions.iter().map(Position::key).collect()
+    }
+
+    // play makes mv, which is assumed to be legal in the current position
+    pub fn play(&mut self, mv: Move) {
+        let next = self.position().apply(mv);
+        self.positions.push(next);
+        self.moves.push(mv);
+    }
+
+    // undo takes back the last move, if any, and returns it
+    pub fn undo(&mut self) -> Option<Move> {
+        let mv = self.moves.pop()?;
+        self.positions.pop();
+        Some(mv)
+    }
+
+    // repetitions returns how many times the current position has occurred,
+    // counting this occurrence
+    pub fn repetitions(&self) -> usize {
+        repetitions(&self.keys(), self.position().halfmove())
+    }
+
+    pub fn status(&self) -> GameStatus {
+        let pos = self.position();
+        if pos.generate_legal().is_empty() {
+            return if pos.in_check() {
+                GameStatus::Checkmate {
+                    winner: pos.side().other(),
+                }
+            } else {
+                GameStatus::Stalemate
+            };
+        }
+
+        // the automatic draws come before the ones which need a claim
+        let repetitions = self.repetitions();
+        if repetitions >= 5 {
+            GameStatus::FivefoldRepetition
+        } else if pos.halfmove() >= 150 {
+            GameStatus::SeventyFiveMoveRule
+        } else if pos.has_insufficient_material() {
+            GameStatus::InsufficientMaterial
+        } else if repetitions >= 3 {
+            GameStatus::ThreefoldRepetition
+        } else if pos.halfmove() >= 100 {
+            GameStatus::FiftyMoveRule
+        } else {
+            GameStatus::Ongoing
+        }
+    }
+}
+
+// repetitions counts the keys equal to the last one, itself included. Only the
+// last halfmove plies are looked at, as no position before a capture or pawn
+// move can come back, and only every other ply, as the side to move must match.
+pub fn repetitions(keys: &[u64], halfmove: u16) -> usize {
+    let Some((&last, earlier)) = keys.split_last() else {
+        return 0;
+    };
+    1 + earlier
+        .iter()
+        .rev()
+        .take(halfmove as usize)
+        .skip(1)
+        .step_by(2)
+        .filter(|&&key| key == last)
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{uci, utils};
+
+    fn play(game: &mut Game, moves: &str) {
+        for s in moves.split_whitespace() {
+            let mv = game.position().parse_uci_move(s).unwrap();
+            game.play(mv);
+        }
+    }
+
+    #[test]
+    fn repetition() {
+        let mut game = Game::new(Position::new(uci::STARTPOS));
+        play(&mut game, "g1f3 g8f6 f3g1 f6g8");
+        assert_eq!(game.repetitions(), 2);
+        assert_eq!(game.status(), GameStatus::Ongoing);
+        play(&mut game, "g1f3 g8f6 f3g1 f6g8");
+        assert_eq!(game.status(), GameStatus::ThreefoldRepetition);
+        play(&mut game, "g1f3 g8f6 f3g1 f6g8 g1f3 g8f6 f3g1");
+        assert_eq!(game.status(), GameStatus::ThreefoldRepetition);
+        play(&mut game, "f6g8");
+        assert_eq!(game.repetitions(), 5);
+        assert_eq!(game.status(), GameStatus::FivefoldRepetition);
+
+        // a pawn move rules out any earlier position
+        play(&mut game, "e2e4 e7e5 g1f3 g8f6 f3g1 f6g8");
+        assert_eq!(game.repetitions(), 2);
+
+        assert_eq!(utils::move_string(game.undo().unwrap()), "f6g8");
+        assert_eq!(game.repetitions(), 1);
+        assert_eq!(game.moves().len(), 21);
+        assert_eq!(game.start().to_fen(), uci::STARTPOS);
+    }
+
+    #[test]
+    fn move_count_rules() {
+        let status = |fen: &str, moves: &str| {
+            let mut game = Game::new(Position::new(fen));
+            play(&mut game, moves);
+            game.status()
+        };
+        let fen = "7k/8/8/8/8/8/8/KQ6 w - - 99 80";
+        assert_eq!(status(fen, ""), GameStatus::Ongoing);
+        assert_eq!(status(fen, "b1c2"), GameStatus::FiftyMoveRule);
+        assert_eq!(
+            status("7k/8/8/8/8/8/1p6/KQ6 w - - 99 80", "a1b2"),
+            GameStatus::Ongoing
+        );
+        let fen = "7k/8/8/8/8/8/8/KQ6 w - - 149 80";
+        assert_eq!(status(fen, "b1c2"), GameStatus::SeventyFiveMoveRule);
+
+        // mate on the hundredth ply still wins
+        let fen = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 99 80";
+        assert_eq!(
+            status(fen, "a1a8"),
+            GameStatus::Checkmate {
+                winner: enums::Colour::White
+            }
+        );
+        assert_eq!(
+            status("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", ""),
+            GameStatus::Stalemate
+        );
+    }
+
+    #[test]
+    fn insufficient_material() {
+        for (fen, insufficient) in [
+            ("8/8/4k3/8/8/4K3/8/8 w - - 0 1", true),
+            ("8/8/4k3/8/8/4K3/8/2B5 w - - 0 1", true),
+            ("8/8/4k3/8/8/4K3/8/6n1 w - - 0 1", true),
+            ("5b2/8/4k3/8/8/4K3/8/2B5 w - - 0 1", true),
+            ("2b5/8/4k3/8/8/4K3/8/2B5 w - - 0 1", false),
+            ("8/8/4k3/8/8/4K3/8/1NN5 w - - 0 1", false),
+            ("8/8/4k3/8/8/4K3/8/1N3b2 w - - 0 1", false),
+            ("8/8/4k3/8/8/4K3/4P3/8 w - - 0 1", false),
+            ("8/8/4k3/8/8/4K3/8/7R w - - 0 1", false),
+        ] {
+            let game = Game::new(Position::new(fen));
+            assert_eq!(
+                game.status() == GameStatus::InsufficientMaterial,
+                insufficient,
+                "{}",
+                fen
+            );
+        }
+    }
+}
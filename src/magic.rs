@@ -1,71 +1,27 @@
-use crate::aliases::{Bitboard, Square};
 use crate::masks;
+use crate::types::{Bitboard, Square};
 
-use std::time::UNIX_EPOCH;
+// batk walks the four diagonal rays from sq, each up to and including the
+// first square set in b
+pub fn batk(sq: Square, b: Bitboard) -> Bitboard {
+    rays(sq, b, &[(-1, -1), (-1, 1), (1, -1), (1, 1)])
+}
 
-fn batk(sq: Square, b: Bitboard) -> Bitboard {
-    let mut ret: u64 = 0;
-    let (rk, fl) = (sq / 8, sq % 8);
-    for (r, f) in (0..rk).rev().zip((0..fl).rev()) {
-        let sq = 8 * r + f;
-        ret |= 1u64 << sq;
-        if b & (1 << sq) != 0 {
-            break;
-        }
-    }
-    for (r, f) in (0..rk).rev().zip(fl + 1..8) {
-        let sq = 8 * r + f;
-        ret |= 1u64 << sq;
-        if b & (1 << sq) != 0 {
-            break;
-        }
-    }
-    for (r, f) in (rk + 1..8).zip((0..fl).rev()) {
-        let sq = 8 * r + f;
-        ret |= 1u64 << sq;
-        if b & (1 << sq) != 0 {
-            break;
-        }
-    }
-    for (r, f) in (rk + 1..8).zip(fl + 1..8) {
-        let sq = 8 * r + f;
-        ret |= 1u64 << sq;
-        if b & (1 << sq) != 0 {
-            break;
-        }
-    }
-    ret
+// ratk walks the four orthogonal rays from sq in the same way
+pub fn ratk(sq: Square, b: Bitboard) -> Bitboard {
+    rays(sq, b, &[(-1, 0), (1, 0), (0, -1), (0, 1)])
 }
 
-fn ratk(sq: Square, b: Bitboard) -> Bitboard {
-    let mut ret: u64 = 0;
-    let (rk, fl) = (sq / 8, sq % 8);
-    for r in (0..rk).rev() {
-        let sq = 8 * r + fl;
-        ret |= 1u64 << sq;
-        if b & (1 << sq) != 0 {
-            break;
-        }
-    }
-    for r in rk + 1..8 {
-        let sq = 8 * r + fl;
-        ret |= 1u64 << sq;
-        if b & (1 << sq) != 0 {
-            break;
-        }
-    }
-    for f in (0..fl).rev() {
-        let sq = 8 * rk + f;
-        ret |= 1u64 << sq;
-        if b & (1 << sq) != 0 {
-            break;
-        }
-    }
-    for f in fl + 1..8 {
-        let sq = 8 * rk + f;
-        ret |= 1u64 << sq;
-        if b & (1 << sq) != 0 {
-            break;
+fn rays(sq: Square, b: Bitboard, dirs: &[(i8, i8)]) -> Bitboard {
+    let mut ret = Bitboard::EMPTY;
+    for &(dr, df) in dirs {
+        let mut cur = sq;
+        while let Some(next) = cur.try_offset(dr, df) {
+            ret |= next.bb();
+            if b.contains(next) {
+                break;
+            }
+            cur = next;
         }
     }
     ret
@@ -73,12 +29,16 @@ fn ratk(sq: Square, b: Bitboard) -> Bitboard {
 
 // xorshift* Prng
 // https://en.wikipedia.org/wiki/Xorshift
-struct Prng {
+pub struct Prng {
     state: u64,
 }
 
 impl Prng {
-    fn next(&mut self) -> u64 {
+    pub const fn new(seed: u64) -> Prng {
+        Prng { state: seed }
+    }
+
+    pub const fn next(&mut self) -> u64 {
         self.state ^= self.state >> 12;
         self.state ^= self.state << 25;
         self.state ^= self.state >> 27;
@@ -92,15 +52,49 @@ impl Prng {
     }
 }
 
-#[derive(Default, Copy, Clone)]
+#[derive(Default, Copy, Clone, Debug, Eq, PartialEq)]
 pub struct Magic {
     pub num: u64,
     pub shift: u8,
+
+    // Bits set before multiplying, 0 for plain magics and the complement of
+    // the relevance mask for black magics
+    // See: https://www.chessprogramming.org/Magic_Bitboards#Black_Magic_Bitboards
+    pub fill: u64,
 }
 
 impl Magic {
     pub fn transform(&self, b: Bitboard) -> u64 {
-        u64::wrapping_mul(b, self.num) >> self.shift
+        u64::wrapping_mul(b.0 | self.fill, self.num) >> self.shift
+    }
+}
+
+// Kinds of magic find_mag can look for
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Variant {
+    // shrink the width as far as the budget allows
+    Plain,
+    // as Plain, but with black magics
+    Black,
+    // any magic of exactly this width, so all squares can share one shift
+    Fixed(u8),
+}
+
+// How long find_mag keeps looking for narrower magics on a square
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Budget {
+    // this many candidates, so a seed always gives the same magics
+    Tries(u64),
+    // this much time, so the magics found depend on the machine
+    Time(std::time::Duration),
+}
+
+impl std::fmt::Display for Budget {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Budget::Tries(n) => write!(f, "{} tries", n),
+            Budget::Time(d) => write!(f, "{} ms", d.as_millis()),
+        }
     }
 }
 
@@ -120,19 +114,17 @@ fn check_mag(
     bishop: bool,
 ) -> bool {
     let rel_mask = if bishop {
-        ms.brel[sq as usize]
+        ms.brel[sq.index()]
     } else {
-        ms.rrel[sq as usize]
+        ms.rrel[sq.index()]
     };
-    let mut rel_bits: Bitboard = 0;
 
     // Stack of modified positions in arr, so we can roll back easily
     let mut stack: Vec<usize> = Vec::new();
 
-    // See: https://www.chessprogramming.org/Traversing_Subsets_of_a_Set
     // We will traverse over all possible sets of the relevance mask, checking
     // that all collisions occur only when the attack sets are the same
-    loop {
+    for rel_bits in rel_mask.subsets() {
         let att = if bishop {
             batk(sq, rel_bits)
         } else {
@@ -141,9 +133,9 @@ fn check_mag(
         let hash = mag.transform(rel_bits) as usize;
 
         // Undesirable collision
-        if vec[hash] != 0 && vec[hash] != att {
+        if !vec[hash].is_empty() && vec[hash] != att {
             for i in stack {
-                vec[i] = 0;
+                vec[i] = Bitboard::EMPTY;
             }
             return false;
         }
@@ -151,77 +143,188 @@ fn check_mag(
         // No collision
         vec[hash] = att;
         stack.push(hash);
-
-        rel_bits = u64::wrapping_sub(rel_bits, rel_mask) & rel_mask;
-        if rel_bits == 0 {
-            break;
-        }
     }
     true
 }
 
-// find_mag finds a magic with the shortest possible width in the given duration,
-// along with the corresponding mapping. It is guaranteed to find a magic for the
-// initial width, and might exceed the given duration to do so.
-fn find_mag(
+// fill_mag builds the hash to attack set mapping for a known magic, or returns
+// None if the magic is not valid for the square
+pub fn fill_mag(
     ms: &masks::Lookup,
+    mag: &Magic,
     sq: Square,
-    d: std::time::Duration,
     bishop: bool,
-) -> (Magic, Vec<Bitboard>) {
-    // Seed the PRNG
-    let mut rng = Prng {
-        state: std::time::SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Unexpected SystemTime error")
-            .as_millis() as u64,
-    };
+) -> Option<Vec<Bitboard>> {
+    let mut vec = vec![Bitboard::EMPTY; 1 << (64 - mag.shift)];
+    if check_mag(ms, mag, &mut vec, sq, bishop) {
+        Some(vec)
+    } else {
+        None
+    }
+}
 
-    let start_time = std::time::SystemTime::now();
+// find_mag finds a magic with the shortest possible width within the given
+// budget, along with the corresponding mapping. It is guaranteed to find a magic
+// for the initial width, and might exceed the budget to do so. Magics are drawn
+// from rng, so with Budget::Tries a given seed always gives the same magic.
+// For Variant::Fixed only the given width is tried, and None is returned if no
+// magic of that width turns up within the budget.
+pub fn find_mag(
+    ms: &masks::Lookup,
+    sq: Square,
+    budget: Budget,
+    bishop: bool,
+    variant: Variant,
+    rng: &mut Prng,
+) -> Option<(Magic, Vec<Bitboard>)> {
+    let start_time = std::time::Instant::now();
+    let mut tries = 0;
 
     // Keep track of the best result so far
-    let mut ret_mag: Option<Magic> = None;
-    let mut ret_vec: Vec<Bitboard> = Vec::new();
+    let mut ret: Option<(Magic, Vec<Bitboard>)> = None;
 
     // We will start from the initial width, and lower as we go
     let initial_width = if bishop { 10 } else { 13 };
-    'outer: for width in (1..initial_width + 1).rev() {
-        let mut vec = vec![0; 1 << width];
+    let (widths, fill) = match variant {
+        Variant::Plain => (1..=initial_width, 0),
+        Variant::Black => {
+            let rel_mask = if bishop {
+                ms.brel[sq.index()]
+            } else {
+                ms.rrel[sq.index()]
+            };
+            (1..=initial_width, (!rel_mask).0)
+        }
+        Variant::Fixed(width) => (width..=width, 0),
+    };
+    'outer: for width in widths.rev() {
+        let mut vec = vec![Bitboard::EMPTY; 1 << width];
         loop {
             // Try a random magic
             let mag = Magic {
                 num: rng.next_sparse(),
                 shift: 64 - width,
+                fill,
             };
 
-            // Deadline exceeded, return immediately
-            let dt = std::time::SystemTime::now()
-                .duration_since(start_time)
-                .expect("Unexpected SystemTime error");
-
-            if dt > d && ret_mag.is_some() {
-                break 'outer;
-            }
-
             // This magic works
             if check_mag(ms, &mag, &mut vec, sq, bishop) {
-                ret_mag = Some(mag);
-                ret_vec = vec;
+                ret = Some((mag, vec));
                 break;
             }
+
+            // Budget spent, return the best so far. The initial width of the
+            // other variants always has a magic, so only a fixed width may give
+            // up empty handed.
+            tries += 1;
+            let spent = match budget {
+                Budget::Tries(n) => tries >= n,
+                Budget::Time(d) => start_time.elapsed() > d,
+            };
+            if spent && (ret.is_some() || matches!(variant, Variant::Fixed(_))) {
+                break 'outer;
+            }
         }
     }
+    ret
+}
+
+// min_width returns the fewest index bits able to tell apart the attack sets
+// of a bishop or rook on sq, below which no magic can exist
+pub fn min_width(ms: &masks::Lookup, sq: Square, bishop: bool) -> u8 {
+    let (rel_mask, atk): (Bitboard, fn(Square, Bitboard) -> Bitboard) = if bishop {
+        (ms.brel[sq.index()], batk)
+    } else {
+        (ms.rrel[sq.index()], ratk)
+    };
+    let sets: std::collections::HashSet<Bitboard> = rel_mask
+        .subsets()
+        .map(|rel_bits| atk(sq, rel_bits))
+        .collect();
+    (usize::BITS - (sets.len() - 1).leading_zeros()) as u8
+}
 
-    match ret_mag {
-        Some(mag) => (mag, ret_vec),
-        None => panic!("unexpected panic on magic generation"),
+pub fn find_bmag(
+    ms: &masks::Lookup,
+    sq: Square,
+    budget: Budget,
+    rng: &mut Prng,
+) -> (Magic, Vec<Bitboard>) {
+    find_mag(ms, sq, budget, true, Variant::Plain, rng).expect("plain magics always exist")
+}
+
+pub fn find_rmag(
+    ms: &masks::Lookup,
+    sq: Square,
+    budget: Budget,
+    rng: &mut Prng,
+) -> (Magic, Vec<Bitboard>) {
+    find_mag(ms, sq, budget, false, Variant::Plain, rng).expect("plain magics always exist")
+}
+
+// Magics are saved in a small binary file: the tag below, then the 64 bishop
+// and 64 rook magics in square order, each as the little endian number, the
+// shift and the little endian fill.
+const FILE_TAG: &[u8; 8] = b"RFMAGIC1";
+const ENTRY_SIZE: usize = 17;
+
+// Shifts giving widths of 1 to 16 bits, more than any relevance mask has. A
+// shift outside them would index past the table or size it beyond memory.
+const SHIFTS: std::ops::RangeInclusive<u8> = 48..=63;
+
+pub fn to_bytes(bmag: &[Magic; 64], rmag: &[Magic; 64]) -> Vec<u8> {
+    let mut ret = FILE_TAG.to_vec();
+    for mag in bmag.iter().chain(rmag.iter()) {
+        ret.extend_from_slice(&mag.num.to_le_bytes());
+        ret.push(mag.shift);
+        ret.extend_from_slice(&mag.fill.to_le_bytes());
     }
+    ret
 }
 
-pub fn find_bmag(ms: &masks::Lookup, sq: Square, d: std::time::Duration) -> (Magic, Vec<Bitboard>) {
-    find_mag(ms, sq, d, true)
+pub fn from_bytes(bytes: &[u8]) -> Option<([Magic; 64], [Magic; 64])> {
+    let entries = bytes.strip_prefix(FILE_TAG)?;
+    if entries.len() != 128 * ENTRY_SIZE {
+        return None;
+    }
+    let mut mags = [Magic::default(); 128];
+    for (mag, entry) in mags.iter_mut().zip(entries.chunks_exact(ENTRY_SIZE)) {
+        if !SHIFTS.contains(&entry[8]) {
+            return None;
+        }
+        *mag = Magic {
+            num: u64::from_le_bytes(entry[0..8].try_into().ok()?),
+            shift: entry[8],
+            fill: u64::from_le_bytes(entry[9..17].try_into().ok()?),
+        };
+    }
+    let mut bmag = [Magic::default(); 64];
+    let mut rmag = [Magic::default(); 64];
+    bmag.copy_from_slice(&mags[..64]);
+    rmag.copy_from_slice(&mags[64..]);
+    Some((bmag, rmag))
 }
 
-pub fn find_rmag(ms: &masks::Lookup, sq: Square, d: std::time::Duration) -> (Magic, Vec<Bitboard>) {
-    find_mag(ms, sq, d, false)
+// to_rust writes the magics as a Rust source file in the layout of
+// magic_numbers.rs, headed by the given comment
+pub fn to_rust(bmag: &[Magic; 64], rmag: &[Magic; 64], comment: &str) -> String {
+    let mut ret = String::new();
+    for line in comment.lines() {
+        ret.push_str(&format!("// {}\n", line));
+    }
+    ret.push_str("use crate::magic::Magic;\n");
+    for (name, mags) in [("BISHOP", bmag), ("ROOK", rmag)] {
+        ret.push_str(&format!(
+            "\n#[rustfmt::skip]\npub const {}: [Magic; 64] = [\n",
+            name
+        ));
+        for mag in mags {
+            ret.push_str(&format!(
+                "    Magic {{ num: {:#018x}, shift: {}, fill: {:#018x} }},\n",
+                mag.num, mag.shift, mag.fill
+            ));
+        }
+        ret.push_str("];\n");
+    }
+    ret
 }
@@ -0,0 +1,139 @@
+// Magics for the sliding piece attack tables, found with
+//   ragfish magic-search --seed 0x5eedf15b --tries 1000000 --variant plain
+use crate::magic::Magic;
+
+#[rustfmt::skip]
+pub const BISHOP: [Magic; 64] = [
+    Magic { num: 0x8051200204004010, shift: 58, fill: 0x0000000000000000 },
+    Magic { num: 0x0620020400608000, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x0304010222000000, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x8914040890010548, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x0202021000020500, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x0081042104a0c003, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x0004160910190242, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x1501002f04200430, shift: 58, fill: 0x0000000000000000 },
+    Magic { num: 0x0020202009290102, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x21000418020c0030, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x4000106106410880, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x04000820802000c0, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x04008b10404210c0, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x1002110120113000, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x0091288405284009, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x8000182101082022, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x49108022208b0302, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x0110004206082105, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x0804000808009010, shift: 57, fill: 0x0000000000000000 },
+    Magic { num: 0x022082080201c012, shift: 57, fill: 0x0000000000000000 },
+    Magic { num: 0x0121010090400040, shift: 57, fill: 0x0000000000000000 },
+    Magic { num: 0x0400208500884010, shift: 57, fill: 0x0000000000000000 },
+    Magic { num: 0x0088840104012022, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x2083120041009021, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x005088004008a500, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x0090100002040940, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x020048040b020408, shift: 57, fill: 0x0000000000000000 },
+    Magic { num: 0x10640800192201c0, shift: 55, fill: 0x0000000000000000 },
+    Magic { num: 0x0006840080802008, shift: 55, fill: 0x0000000000000000 },
+    Magic { num: 0x90100300a4815700, shift: 57, fill: 0x0000000000000000 },
+    Magic { num: 0x0004404a01080222, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x0008420000820502, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x0121182000420c40, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x00020844800e9020, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x0033404818100264, shift: 57, fill: 0x0000000000000000 },
+    Magic { num: 0x0b88100820040400, shift: 55, fill: 0x0000000000000000 },
+    Magic { num: 0x0082208400020020, shift: 55, fill: 0x0000000000000000 },
+    Magic { num: 0x0042008604010040, shift: 57, fill: 0x0000000000000000 },
+    Magic { num: 0x5604128083640404, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x1204008460220301, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x08040c042c224084, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x8001080110140444, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x0002010248008108, shift: 57, fill: 0x0000000000000000 },
+    Magic { num: 0x4040170411080800, shift: 57, fill: 0x0000000000000000 },
+    Magic { num: 0x004204052c000601, shift: 57, fill: 0x0000000000000000 },
+    Magic { num: 0x1120042184200601, shift: 57, fill: 0x0000000000000000 },
+    Magic { num: 0x20101002014a8088, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x000800a402406080, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x4401040120880442, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x8088208a1090000d, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x0000006084100004, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x00020408a0880000, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x44c2214010431080, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x000020220a620012, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x1011200114008845, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x4508100400802020, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x4002450088200200, shift: 58, fill: 0x0000000000000000 },
+    Magic { num: 0x4500008881901000, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x0020001884008828, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x4402020000420a00, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x00008a4440050b00, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x0480001021030100, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x0400080821080200, shift: 59, fill: 0x0000000000000000 },
+    Magic { num: 0x452204100c010021, shift: 58, fill: 0x0000000000000000 },
+];
+
+#[rustfmt::skip]
+pub const ROOK: [Magic; 64] = [
+    Magic { num: 0x8480004000802811, shift: 52, fill: 0x0000000000000000 },
+    Magic { num: 0x254010004000e004, shift: 53, fill: 0x0000000000000000 },
+    Magic { num: 0x0200081200802040, shift: 53, fill: 0x0000000000000000 },
+    Magic { num: 0x0080080080041000, shift: 53, fill: 0x0000000000000000 },
+    Magic { num: 0x0200201002001448, shift: 53, fill: 0x0000000000000000 },
+    Magic { num: 0x4500340002010008, shift: 53, fill: 0x0000000000000000 },
+    Magic { num: 0x22000800a4010200, shift: 53, fill: 0x0000000000000000 },
+    Magic { num: 0x0200002104408402, shift: 52, fill: 0x0000000000000000 },
+    Magic { num: 0x008080014001812b, shift: 53, fill: 0x0000000000000000 },
+    Magic { num: 0x804040100340a002, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x8020808020001000, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x1082002200c00810, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x1000800800800400, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x0101000900040002, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x4002008904080200, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x0002000b0a854402, shift: 53, fill: 0x0000000000000000 },
+    Magic { num: 0xa000a38000400280, shift: 53, fill: 0x0000000000000000 },
+    Magic { num: 0x4810004004406001, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x080180801000200c, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x0a06808010040800, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x0058010009000410, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x000080800a000400, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x0004808002000100, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x2a00020001440083, shift: 53, fill: 0x0000000000000000 },
+    Magic { num: 0x0085400080018925, shift: 53, fill: 0x0000000000000000 },
+    Magic { num: 0x004100810040022c, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0xc210048280200010, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x0002201a00120040, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x243008010011000c, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x0081140080460080, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x0000a904001008a2, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x98010001000080c2, shift: 53, fill: 0x0000000000000000 },
+    Magic { num: 0x0020c00628800080, shift: 53, fill: 0x0000000000000000 },
+    Magic { num: 0x8010102000400441, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x2001002001004010, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x0220100009002100, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x2400080101002410, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x0102000402000810, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x248008021c008150, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x08a2070042002294, shift: 53, fill: 0x0000000000000000 },
+    Magic { num: 0x1040008020408000, shift: 53, fill: 0x0000000000000000 },
+    Magic { num: 0x0461008040150020, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x0001002002c30010, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x0411009850010020, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x0a00280100110005, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x0002000508020010, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x0ac4082610540001, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x0010004405820001, shift: 53, fill: 0x0000000000000000 },
+    Magic { num: 0x4040284102008200, shift: 53, fill: 0x0000000000000000 },
+    Magic { num: 0x0841049022400100, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x4090422050820200, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x0a30021100082100, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x0020826401080080, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x300140a004100801, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x02210086001c0100, shift: 54, fill: 0x0000000000000000 },
+    Magic { num: 0x801040448c010200, shift: 53, fill: 0x0000000000000000 },
+    Magic { num: 0x0100110040220082, shift: 52, fill: 0x0000000000000000 },
+    Magic { num: 0x000080c200102102, shift: 53, fill: 0x0000000000000000 },
+    Magic { num: 0x0001800822104202, shift: 53, fill: 0x0000000000000000 },
+    Magic { num: 0x0001090004211001, shift: 53, fill: 0x0000000000000000 },
+    Magic { num: 0x3103004610180005, shift: 53, fill: 0x0000000000000000 },
+    Magic { num: 0x0a11001400221819, shift: 53, fill: 0x0000000000000000 },
+    Magic { num: 0x4202001804359102, shift: 53, fill: 0x0000000000000000 },
+    Magic { num: 0x00080104802c0842, shift: 52, fill: 0x0000000000000000 },
+];
@@ -0,0 +1,388 @@
+use crate::magic::{self, Budget, Magic, Variant};
+use crate::masks;
+use crate::types::Square;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// magic-search looks for magics offline and saves them for tables::Magics,
+// either as Rust source to replace magic_numbers.rs or as a binary file for
+// tables::Magics::load, picked by the extension of --out. The search on each
+// square is limited to --tries candidates, which gives the same magics for a
+// seed on any machine, or to --millis of time, which does not.
+//
+// ragfish magic-search [--seed N] [--tries N | --millis N] [--threads N]
+//                      [--variant plain|black|fixed] [--bishop-bits N]
+//                      [--rook-bits N] [--out PATH]
+const USAGE: &str =
+    "usage: ragfish magic-search [--seed N] [--tries N | --millis N] [--threads N] \
+                     [--variant plain|black|fixed] [--bishop-bits N] [--rook-bits N] [--out PATH]";
+
+// candidates tried on each square unless told otherwise
+const DEFAULT_TRIES: u64 = 1_000_000;
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct Options {
+    pub seed: u64,
+    // spent shrinking the width of each square's magic, or for fixed widths
+    // the most spent looking for one
+    pub budget: Budget,
+    pub threads: usize,
+    pub bishop: Variant,
+    pub rook: Variant,
+    pub out: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            seed: 0x5eedf15b,
+            budget: Budget::Tries(DEFAULT_TRIES),
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            bishop: Variant::Plain,
+            rook: Variant::Plain,
+            out: None,
+        }
+    }
+}
+
+pub fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut ret = Options::default();
+    let (mut variant, mut bishop_bits, mut rook_bits) = ("plain", 9, 12);
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or(format!("{} needs a value", flag))?;
+        let number = || {
+            match value.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None => value.parse::<u64>(),
+            }
+            .map_err(|_| format!("bad value {} for {}", value, flag))
+        };
+        match flag.as_str() {
+            "--seed" => ret.seed = number()?,
+            "--tries" => ret.budget = Budget::Tries(number()?),
+            "--millis" => ret.budget = Budget::Time(Duration::from_millis(number()?)),
+            "--threads" => ret.threads = number()?.max(1) as usize,
+            "--variant" => variant = value,
+            "--bishop-bits" => bishop_bits = number()?.clamp(1, 16) as u8,
+            "--rook-bits" => rook_bits = number()?.clamp(1, 16) as u8,
+            "--out" => ret.out = Some(value.clone()),
+            _ => return Err(format!("unknown flag {}", flag)),
+        }
+    }
+    (ret.bishop, ret.rook) = match variant {
+        "plain" => (Variant::Plain, Variant::Plain),
+        "black" => (Variant::Black, Variant::Black),
+        "fixed" => (Variant::Fixed(bishop_bits), Variant::Fixed(rook_bits)),
+        _ => return Err(format!("unknown variant {}", variant)),
+    };
+
+    // a fixed width must hold the attack sets of the busiest square
+    let ms = masks::Lookup::new();
+    for (name, variant, bishop) in [("bishop", ret.bishop, true), ("rook", ret.rook, false)] {
+        if let Variant::Fixed(bits) = variant {
+            let needed = Square::all()
+                .map(|sq| magic::min_width(&ms, sq, bishop))
+                .max()
+                .unwrap_or(1);
+            if bits < needed {
+                return Err(format!(
+                    "--{}-bits {} is too few, {} attack sets need {}",
+                    name, bits, name, needed
+                ));
+            }
+        }
+    }
+    Ok(ret)
+}
+
+pub fn run(args: &[String]) {
+    let opts = match parse_args(args) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let ms = masks::Lookup::new();
+    let (bmag, rmag) = match search(&ms, &opts) {
+        Ok(mags) => mags,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let entries: usize = bmag
+        .iter()
+        .chain(rmag.iter())
+        .map(|m| 1 << (64 - m.shift))
+        .sum();
+    for (name, mags) in [("bishop", &bmag), ("rook", &rmag)] {
+        let widths: Vec<String> = mags.iter().map(|m| (64 - m.shift).to_string()).collect();
+        eprintln!("{} widths: {}", name, widths.join(" "));
+    }
+    eprintln!(
+        "table entries: {} ({} KiB)",
+        entries,
+        entries * std::mem::size_of::<u64>() / 1024
+    );
+
+    let comment = format!(
+        "Magics for the sliding piece attack tables, found with\n  ragfish magic-search {}",
+        command_line(&opts)
+    );
+    let result = match &opts.out {
+        None => {
+            print!("{}", magic::to_rust(&bmag, &rmag, &comment));
+            Ok(())
+        }
+        Some(path) if path.ends_with(".rs") => {
+            std::fs::write(path, magic::to_rust(&bmag, &rmag, &comment))
+        }
+        Some(path) => std::fs::write(path, magic::to_bytes(&bmag, &rmag)),
+    };
+    if let Err(e) = result {
+        eprintln!("could not write magics: {}", e);
+        std::process::exit(1);
+    }
+}
+
+// command_line gives the flags reproducing a search, apart from the thread count
+fn command_line(opts: &Options) -> String {
+    let variant = match (opts.bishop, opts.rook) {
+        (Variant::Fixed(b), Variant::Fixed(r)) => {
+            format!("fixed --bishop-bits {} --rook-bits {}", b, r)
+        }
+        (Variant::Black, _) => String::from("black"),
+        _ => String::from("plain"),
+    };
+    let budget = match opts.budget {
+        Budget::Tries(n) => format!("--tries {}", n),
+        Budget::Time(d) => format!("--millis {}", d.as_millis()),
+    };
+    format!("--seed {:#x} {} --variant {}", opts.seed, budget, variant)
+}
+
+// search finds magics for all 128 bishop and rook squares, spread over the
+// requested number of threads. Each square draws from its own generator seeded
+// from the square, so results do not depend on how squares land on threads.
+// It fails if a square has no magic of a fixed width within the budget.
+pub fn search(ms: &masks::Lookup, opts: &Options) -> Result<([Magic; 64], [Magic; 64]), String> {
+    let found = Mutex::new([None; 128]);
+    let next = AtomicUsize::new(0);
+
+    std::thread::scope(|s| {
+        for _ in 0..opts.threads {
+            s.spawn(|| loop {
+                let job = next.fetch_add(1, Ordering::Relaxed);
+                if job >= 128 {
+                    break;
+                }
+                let (sq, bishop) = (Square::new((job % 64) as u8), job < 64);
+                let variant = if bishop { opts.bishop } else { opts.rook };
+                // xorshift never leaves 0, so keep the seed odd
+                let mut rng = magic::Prng::new(
+                    (opts.seed ^ (job as u64).wrapping_mul(0x9e3779b97f4a7c15)) | 1,
+                );
+                let mag = magic::find_mag(ms, sq, opts.budget, bishop, variant, &mut rng)
+                    .map(|(mag, _)| mag);
+                if let Some(mag) = mag {
+                    eprintln!(
+                        "{} {} width {}",
+                        if bishop { "bishop" } else { "rook" },
+                        sq,
+                        64 - mag.shift
+                    );
+                }
+                found.lock().expect("magic search thread panicked")[job] = mag;
+            });
+        }
+    });
+
+    let found = found.into_inner().expect("magic search thread panicked");
+    let mut bmag = [Magic::default(); 64];
+    let mut rmag = [Magic::default(); 64];
+    for (job, mag) in found.into_iter().enumerate() {
+        let (sq, bishop) = (Square::new((job % 64) as u8), job < 64);
+        let Some(mag) = mag else {
+            let (name, variant) = if bishop {
+                ("bishop", opts.bishop)
+            } else {
+                ("rook", opts.rook)
+            };
+            return Err(format!(
+                "no {} magic for {} found in {} with {:?}",
+                name, sq, opts.budget, variant
+            ));
+        };
+        if bishop {
+            bmag[sq.index()] = mag;
+        } else {
+            rmag[sq.index()] = mag;
+        }
+    }
+    Ok((bmag, rmag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magic_numbers;
+    use crate::tables::{self, SliderAttacks};
+    use crate::types::Bitboard;
+    use crate::utils::tests::args;
+
+    #[test]
+    fn parses_flags() {
+        let opts = parse_args(&args(
+            "--seed 42 --millis 5 --threads 3 --variant fixed --bishop-bits 10 --out m.bin",
+        ))
+        .unwrap();
+        assert_eq!(
+            opts,
+            Options {
+                seed: 42,
+                budget: Budget::Time(Duration::from_millis(5)),
+                threads: 3,
+                bishop: Variant::Fixed(10),
+                rook: Variant::Fixed(12),
+                out: Some(String::from("m.bin")),
+            }
+        );
+        assert_eq!(
+            parse_args(&args("--variant black")).unwrap().rook,
+            Variant::Black
+        );
+        assert_eq!(parse_args(&args("--seed 0x2a")).unwrap().seed, 42);
+        assert_eq!(
+            parse_args(&args("--tries 500")).unwrap().budget,
+            Budget::Tries(500)
+        );
+        assert!(parse_args(&args("--variant grey")).is_err());
+        assert!(parse_args(&args("--seed")).is_err());
+        assert!(parse_args(&args("--colour red")).is_err());
+
+        // the centre squares have 108 bishop and 144 rook attack sets
+        let fixed = |bits: &str| parse_args(&args(&format!("--variant fixed {}", bits)));
+        assert!(fixed("--bishop-bits 7 --rook-bits 8").is_ok());
+        assert_eq!(
+            fixed("--rook-bits 7"),
+            Err(String::from(
+                "--rook-bits 7 is too few, rook attack sets need 8"
+            ))
+        );
+        assert!(fixed("--bishop-bits 6").is_err());
+    }
+
+    #[test]
+    fn reports_squares_without_magics() {
+        let ms = masks::Lookup::new();
+        let mut rng = magic::Prng::new(1);
+        let none = magic::find_mag(
+            &ms,
+            Square::A1,
+            Budget::Tries(0),
+            false,
+            Variant::Fixed(4),
+            &mut rng,
+        );
+        assert!(none.is_none());
+
+        // a width allowed by the attack sets, but not in the tries given
+        let opts = Options {
+            budget: Budget::Tries(0),
+            threads: 2,
+            bishop: Variant::Black,
+            rook: Variant::Fixed(8),
+            ..Default::default()
+        };
+        assert_eq!(
+            search(&ms, &opts),
+            Err(String::from(
+                "no rook magic for a1 found in 0 tries with Fixed(8)"
+            ))
+        );
+    }
+
+    #[test]
+    fn command_line_round_trips() {
+        for cmd in [
+            "--seed 0x2a --millis 7 --variant black",
+            "--seed 0x5eedf15b --tries 1000000 --variant plain",
+            "--seed 0x1 --tries 0 --variant fixed --bishop-bits 8 --rook-bits 11",
+        ] {
+            assert_eq!(command_line(&parse_args(&args(cmd)).unwrap()), cmd);
+        }
+    }
+
+    #[test]
+    fn saved_magics_load_into_tables() {
+        let ms = masks::Lookup::new();
+        // black magics settle for the initial width at once, fixed ones stop as
+        // soon as they are found
+        for (variant, tries) in [("black", 0), ("fixed", u64::MAX)] {
+            let opts = parse_args(&args(&format!(
+                "--tries {} --threads 2 --variant {}",
+                tries, variant
+            )))
+            .unwrap();
+            let (bmag, rmag) = search(&ms, &opts).unwrap();
+            if variant == "fixed" {
+                assert!(bmag.iter().all(|m| m.shift == 64 - 9));
+                assert!(rmag.iter().all(|m| m.shift == 64 - 12));
+            }
+
+            let path = std::env::temp_dir().join(format!("ragfish-magics-{}.bin", variant));
+            std::fs::write(&path, magic::to_bytes(&bmag, &rmag)).unwrap();
+            let t = tables::Magics::load(&ms, &path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            for sq in Square::all() {
+                let empty = Bitboard::EMPTY;
+                assert_eq!(t.bishop_attacks(&ms, sq, empty), magic::batk(sq, empty));
+                assert_eq!(t.rook_attacks(&ms, sq, empty), magic::ratk(sq, empty));
+            }
+        }
+    }
+
+    #[test]
+    fn rust_output_round_trips_shipped_magics() {
+        let src = magic::to_rust(&magic_numbers::BISHOP, &magic_numbers::ROOK, "x");
+        assert!(src.starts_with("// x\nuse crate::magic::Magic;\n"));
+        for mag in magic_numbers::BISHOP
+            .iter()
+            .chain(magic_numbers::ROOK.iter())
+        {
+            assert!(src.contains(&format!("num: {:#018x}, shift: {}", mag.num, mag.shift)));
+        }
+        let bytes = magic::to_bytes(&magic_numbers::BISHOP, &magic_numbers::ROOK);
+        assert_eq!(
+            magic::from_bytes(&bytes),
+            Some((magic_numbers::BISHOP, magic_numbers::ROOK))
+        );
+        assert_eq!(magic::from_bytes(&bytes[1..]), None);
+    }
+
+    #[test]
+    fn rejects_corrupt_shifts() {
+        let ms = masks::Lookup::new();
+        let path = std::env::temp_dir().join("ragfish-magics-corrupt.bin");
+        for shift in [0, 47, 64, 200] {
+            let mut bytes = magic::to_bytes(&magic_numbers::BISHOP, &magic_numbers::ROOK);
+            // the shift of the first rook magic, after the tag and the bishops
+            bytes[8 + 64 * 17 + 8] = shift;
+            assert_eq!(magic::from_bytes(&bytes), None);
+
+            std::fs::write(&path, &bytes).unwrap();
+            let err = tables::Magics::load(&ms, &path).err();
+            assert_eq!(
+                err,
+                Some(format!("{} is not a magics file", path.display()))
+            );
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
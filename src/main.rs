@@ -1,11 +1,46 @@
 #![allow(dead_code)]
 
-mod aliases;
+mod attacks;
+mod bench;
 mod enums;
+mod epd;
+mod eval;
+mod game;
 mod magic;
+mod magic_numbers;
+mod magic_search;
 mod masks;
+mod movelist;
+#[cfg(target_arch = "x86_64")]
+mod pext;
+mod pgn;
 mod positions;
+mod san;
+mod search;
+mod sliders;
+mod suite;
 mod tables;
+mod types;
+mod uci;
 mod utils;
+mod zobrist;
 
-fn main() {}
+#[cfg(all(feature = "pext", not(target_arch = "x86_64")))]
+compile_error!("the pext feature needs an x86_64 target");
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        None => uci::run(),
+        Some("magic-search") => magic_search::run(&args[1..]),
+        Some("bench") => bench::run(&args[1..]),
+        Some("suite") => suite::run(&args[1..]),
+        Some(cmd) => {
+            eprintln!(
+                "unknown command {}, expected magic-search, bench, suite or no command for uci",
+                cmd
+            );
+            std::process::exit(2);
+        }
+    }
+}
@@ -1,5 +1,5 @@
-use crate::aliases::Bitboard;
 use crate::enums;
+use crate::types::Bitboard;
 
 pub struct Lookup {
     // Mask a square
@@ -31,20 +31,20 @@ pub struct Lookup {
 impl Lookup {
     pub fn new() -> Lookup {
         let mut ms = Lookup {
-            sq: [0u64; 64],
-            rank: [0u64; 8],
-            file: [0u64; 8],
-            diag: [0u64; 15],
-            adiag: [0u64; 15],
-            brel: [0u64; 64],
-            rrel: [0u64; 64],
-            king: [0u64; 64],
-            knight: [0u64; 64],
-            pcapture: [[0; 64]; 2],
+            sq: [Bitboard::EMPTY; 64],
+            rank: [Bitboard::EMPTY; 8],
+            file: [Bitboard::EMPTY; 8],
+            diag: [Bitboard::EMPTY; 15],
+            adiag: [Bitboard::EMPTY; 15],
+            brel: [Bitboard::EMPTY; 64],
+            rrel: [Bitboard::EMPTY; 64],
+            king: [Bitboard::EMPTY; 64],
+            knight: [Bitboard::EMPTY; 64],
+            pcapture: [[Bitboard::EMPTY; 64]; 2],
         };
 
         for sq in 0..64 {
-            ms.sq[sq] = 1u64 << sq;
+            ms.sq[sq] = Bitboard(1 << sq);
         }
 
         for rk in 0..8 {
@@ -106,7 +106,7 @@ impl Lookup {
         for sq in 0..64 {
             let (rk, fl): (i8, i8) = ((sq / 8) as i8, (sq % 8) as i8);
             for r in 0.max(rk - 1)..8.min(rk + 2) {
-                for f in 0.max(fl as i8 - 1)..8.min(fl + 2) {
+                for f in 0.max(fl - 1)..8.min(fl + 2) {
                     if r != rk || f != fl {
                         ms.king[sq] |= ms.sq[(8 * r + f) as usize];
                     }
@@ -128,16 +128,16 @@ impl Lookup {
         for sq in 0..64 {
             let (rk, fl): (i8, i8) = ((sq / 8) as i8, (sq % 8) as i8);
             if rk + 1 < 8 && fl > 0 {
-                ms.pcapture[enums::Colour::White as usize][sq as usize] |= 1u64 << (sq + 7);
+                ms.pcapture[enums::Colour::White as usize][sq] |= ms.sq[sq + 7];
             }
             if rk + 1 < 8 && fl + 1 < 8 {
-                ms.pcapture[enums::Colour::White as usize][sq as usize] |= 1u64 << (sq + 9);
+                ms.pcapture[enums::Colour::White as usize][sq] |= ms.sq[sq + 9];
             }
             if rk > 0 && fl + 1 < 8 {
-                ms.pcapture[enums::Colour::Black as usize][sq as usize] |= 1u64 << (sq - 7);
+                ms.pcapture[enums::Colour::Black as usize][sq] |= ms.sq[sq - 7];
             }
             if rk > 0 && fl > 0 {
-                ms.pcapture[enums::Colour::Black as usize][sq as usize] |= 1u64 << (sq - 9);
+                ms.pcapture[enums::Colour::Black as usize][sq] |= ms.sq[sq - 9];
             }
         }
 
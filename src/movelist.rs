@@ -0,0 +1,115 @@
+use crate::types::Move;
+
+// No legal position has more than 218 moves, so generated moves fit in a
+// fixed array on the stack
+// See: https://www.chessprogramming.org/Encoding_Moves#MoveIndex
+pub const CAPACITY: usize = 256;
+
+#[derive(Clone)]
+pub struct MoveList {
+    moves: [Move; CAPACITY],
+    len: usize,
+}
+
+impl MoveList {
+    pub fn new() -> MoveList {
+        MoveList {
+            moves: [Move::default(); CAPACITY],
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, mv: Move) {
+        self.moves[self.len] = mv;
+        self.len += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    // retain keeps the moves for which keep returns true, in order
+    pub fn retain(&mut self, mut keep: impl FnMut(Move) -> bool) {
+        let mut kept = 0;
+        for i in 0..self.len {
+            if keep(self.moves[i]) {
+                self.moves[kept] = self.moves[i];
+                kept += 1;
+            }
+        }
+        self.len = kept;
+    }
+}
+
+impl Default for MoveList {
+    fn default() -> MoveList {
+        MoveList::new()
+    }
+}
+
+impl std::ops::Deref for MoveList {
+    type Target = [Move];
+
+    fn deref(&self) -> &[Move] {
+        &self.moves[..self.len]
+    }
+}
+
+impl std::ops::DerefMut for MoveList {
+    fn deref_mut(&mut self) -> &mut [Move] {
+        &mut self.moves[..self.len]
+    }
+}
+
+impl std::fmt::Debug for MoveList {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl IntoIterator for MoveList {
+    type Item = Move;
+    type IntoIter = std::iter::Take<std::array::IntoIter<Move, CAPACITY>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.moves.into_iter().take(self.len)
+    }
+}
+
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a Move;
+    type IntoIter = std::slice::Iter<'a, Move>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Square;
+
+    fn mv(to: u8) -> Move {
+        Move::new(Square::A1, Square::new(to), 0)
+    }
+
+    #[test]
+    fn push_and_retain() {
+        let mut list = MoveList::new();
+        assert!(list.is_empty());
+        for to in 1..=10 {
+            list.push(mv(to));
+        }
+        assert_eq!(list.len(), 10);
+        list.retain(|m| m.to().index() % 3 == 0);
+        assert_eq!(&list[..], &[mv(3), mv(6), mv(9)]);
+        list.sort_by_key(|m| std::cmp::Reverse(m.to()));
+        assert_eq!(
+            list.clone().into_iter().collect::<Vec<_>>(),
+            vec![mv(9), mv(6), mv(3)]
+        );
+        list.clear();
+        assert_eq!(list.len(), 0);
+    }
+}
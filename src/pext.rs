@@ -0,0 +1,139 @@
+use crate::tables::{self, SliderAttacks};
+use crate::types::{Bitboard, Square};
+use crate::{magic, masks};
+
+use std::arch::x86_64::_pext_u64;
+
+// Slider attacks indexed by extracting the relevant occupancy bits with the
+// BMI2 PEXT instruction, in place of the magic multiply and shift. Each square's
+// table holds exactly 1 << popcount(relevance mask) entries.
+// See: https://www.chessprogramming.org/BMI2#PEXTBitboards
+pub struct Lookup {
+    pub boff: [usize; 64],
+    pub roff: [usize; 64],
+    pub attacks: Vec<Bitboard>,
+}
+
+// is_available tells whether the CPU running us has BMI2
+pub fn is_available() -> bool {
+    std::is_x86_feature_detected!("bmi2")
+}
+
+#[target_feature(enable = "bmi2")]
+unsafe fn pext(b: Bitboard, mask: Bitboard) -> u64 {
+    _pext_u64(b.0, mask.0)
+}
+
+// The backend of the pext feature: PEXT tables when the CPU has BMI2, and the
+// magics otherwise, so that a pext build still runs on older CPUs. There is
+// only ever the one in attacks, so its size does not matter, and boxing would
+// cost every lookup an indirection.
+#[allow(clippy::large_enum_variant)]
+pub enum Backend {
+    Pext(Lookup),
+    Magics(tables::Magics),
+}
+
+impl Backend {
+    pub fn new(ms: &masks::Lookup) -> Backend {
+        if is_available() {
+            Backend::Pext(Lookup::new(ms))
+        } else {
+            Backend::Magics(tables::Magics::new(ms))
+        }
+    }
+}
+
+impl SliderAttacks for Backend {
+    fn bishop_attacks(&self, ms: &masks::Lookup, sq: Square, occ: Bitboard) -> Bitboard {
+        match self {
+            Backend::Pext(t) => t.bishop_attacks(ms, sq, occ),
+            Backend::Magics(t) => t.bishop_attacks(ms, sq, occ),
+        }
+    }
+
+    fn rook_attacks(&self, ms: &masks::Lookup, sq: Square, occ: Bitboard) -> Bitboard {
+        match self {
+            Backend::Pext(t) => t.rook_attacks(ms, sq, occ),
+            Backend::Magics(t) => t.rook_attacks(ms, sq, occ),
+        }
+    }
+
+    fn memory(&self) -> usize {
+        match self {
+            Backend::Pext(t) => t.memory(),
+            Backend::Magics(t) => t.memory(),
+        }
+    }
+}
+
+impl Lookup {
+    // new builds the tables, panicking when the CPU lacks BMI2, as every
+    // lookup would then fault. Backend picks the magics instead.
+    pub fn new(ms: &masks::Lookup) -> Lookup {
+        assert!(is_available(), "the pext feature needs a CPU with BMI2");
+        let mut ret = Lookup {
+            boff: [0; 64],
+            roff: [0; 64],
+            attacks: Vec::new(),
+        };
+        for sq in Square::all() {
+            ret.boff[sq.index()] = ret.fill(ms.brel[sq.index()], |occ| magic::batk(sq, occ));
+        }
+        for sq in Square::all() {
+            ret.roff[sq.index()] = ret.fill(ms.rrel[sq.index()], |occ| magic::ratk(sq, occ));
+        }
+        ret
+    }
+
+    // fill appends the table for one relevance mask, returning its offset
+    fn fill(&mut self, rel: Bitboard, atk: impl Fn(Bitboard) -> Bitboard) -> usize {
+        let off = self.attacks.len();
+        self.attacks
+            .resize(off + (1 << rel.count()), Bitboard::EMPTY);
+        for occ in rel.subsets() {
+            // SAFETY: new checked that the CPU has BMI2
+            let idx = unsafe { pext(occ, rel) } as usize;
+            self.attacks[off + idx] = atk(occ);
+        }
+        off
+    }
+}
+
+impl SliderAttacks for Lookup {
+    fn bishop_attacks(&self, ms: &masks::Lookup, sq: Square, occ: Bitboard) -> Bitboard {
+        // SAFETY: new checked that the CPU has BMI2
+        let idx = unsafe { pext(occ, ms.brel[sq.index()]) };
+        self.attacks[self.boff[sq.index()] + idx as usize]
+    }
+
+    fn rook_attacks(&self, ms: &masks::Lookup, sq: Square, occ: Bitboard) -> Bitboard {
+        // SAFETY: new checked that the CPU has BMI2
+        let idx = unsafe { pext(occ, ms.rrel[sq.index()]) };
+        self.attacks[self.roff[sq.index()] + idx as usize]
+    }
+
+    fn memory(&self) -> usize {
+        std::mem::size_of::<Lookup>() + self.attacks.len() * std::mem::size_of::<Bitboard>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_reference_attacks() {
+        let ms = masks::Lookup::new();
+        let backend = Backend::new(&ms);
+        tables::tests::check_reference_attacks(&ms, &backend);
+        // the magics were checked in place of the PEXT tables without BMI2
+        match backend {
+            Backend::Pext(t) => {
+                assert!(is_available());
+                assert_eq!(t.attacks.len(), 5248 + 102400);
+            }
+            Backend::Magics(_) => assert!(!is_available()),
+        }
+    }
+}
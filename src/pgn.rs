@@ -0,0 +1,643 @@
+use crate::game::{Game, GameStatus};
+use crate::positions::{FenError, Position};
+use crate::san::{self, SanError};
+use crate::{enums, uci};
+
+use std::io::BufRead;
+
+// Portable Game Notation, read a game at a time from a stream of any length
+// and written back in export format
+// See: https://www.chessprogramming.org/Portable_Game_Notation
+// See: https://ia802908.us.archive.org/26/items/pgn-standard-1994-03-12/PGN_standard_1994-03-12.txt
+
+// The tags every exported game starts with, in this order
+const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+// Export format keeps lines shorter than this
+const LINE_LENGTH: usize = 80;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    // still going, abandoned or not known
+    Unknown,
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Outcome::WhiteWins => write!(f, "1-0"),
+            Outcome::BlackWins => write!(f, "0-1"),
+            Outcome::Draw => write!(f, "1/2-1/2"),
+            Outcome::Unknown => write!(f, "*"),
+        }
+    }
+}
+
+fn outcome(s: &str) -> Option<Outcome> {
+    match s {
+        "1-0" => Some(Outcome::WhiteWins),
+        "0-1" => Some(Outcome::BlackWins),
+        "1/2-1/2" => Some(Outcome::Draw),
+        "*" => Some(Outcome::Unknown),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+pub enum PgnError {
+    Io(std::io::Error),
+    // A character or token that has no place where it is
+    Syntax {
+        line: usize,
+    },
+    Fen {
+        line: usize,
+        error: FenError,
+    },
+    Move {
+        line: usize,
+        text: String,
+        error: SanError,
+    },
+    // A variation with no move before it to replace, or unbalanced parentheses
+    Variation {
+        line: usize,
+    },
+}
+
+impl std::fmt::Display for PgnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PgnError::Io(e) => write!(f, "{}", e),
+            PgnError::Syntax { line } => write!(f, "line {}: syntax error", line),
+            PgnError::Fen { line, error } => write!(f, "line {}: bad FEN tag: {}", line, error),
+            PgnError::Move { line, text, error } => {
+                write!(f, "line {}: {}: {}", line, text, error)
+            }
+            PgnError::Variation { line } => write!(f, "line {}: misplaced variation", line),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+// A game as recorded in PGN. Comments, annotations and variations are checked
+// when read but not kept.
+#[derive(Clone)]
+pub struct PgnGame {
+    // tag pairs in the order they were given
+    pub tags: Vec<(String, String)>,
+    pub game: Game,
+    pub result: Outcome,
+}
+
+impl PgnGame {
+    // new records game without tags, with the result if the game is over by
+    // itself, not counting draws that need a claim
+    pub fn new(game: Game) -> PgnGame {
+        let result = match game.status() {
+            GameStatus::Checkmate {
+                winner: enums::Colour::White,
+            } => Outcome::WhiteWins,
+            GameStatus::Checkmate {
+                winner: enums::Colour::Black,
+            } => Outcome::BlackWins,
+            GameStatus::Stalemate
+            | GameStatus::InsufficientMaterial
+            | GameStatus::FivefoldRepetition
+            | GameStatus::SeventyFiveMoveRule => Outcome::Draw,
+            _ => Outcome::Unknown,
+        };
+        PgnGame {
+            tags: Vec::new(),
+            game,
+            result,
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // to_pgn writes the game in export format: the seven tag roster, the other
+    // tags, the start position when it is not the usual one, then the moves in
+    // SAN and the result
+    pub fn to_pgn(&self) -> String {
+        let mut ret = String::new();
+        let mut tag = |name: &str, value: &str| {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            ret.push_str(&format!("[{} \"{}\"]\n", name, value));
+        };
+        for name in SEVEN_TAG_ROSTER {
+            match name {
+                "Result" => tag(name, &self.result.to_string()),
+                "Date" => tag(name, self.tag(name).unwrap_or("????.??.??")),
+                _ => tag(name, self.tag(name).unwrap_or("?")),
+            }
+        }
+        let start = self.game.start();
+        let fen = start.to_fen();
+        for (name, value) in &self.tags {
+            if !SEVEN_TAG_ROSTER.contains(&name.as_str()) && name != "SetUp" && name != "FEN" {
+                tag(name, value);
+            }
+        }
+        if fen != uci::STARTPOS {
+            tag("SetUp", "1");
+            tag("FEN", &fen);
+        }
+        ret.push('\n');
+
+        let mut words = Vec::new();
+        let mut pos = *start;
+        for (i, &mv) in self.game.moves().iter().enumerate() {
+            // a move number stays on the line of its move
+            let san = san::format(&pos, mv);
+            words.push(match pos.side() {
+                enums::Colour::White => format!("{}. {}", pos.fullmove(), san),
+                enums::Colour::Black if i == 0 => format!("{}... {}", pos.fullmove(), san),
+                enums::Colour::Black => san,
+            });
+            pos = pos.apply(mv);
+        }
+        words.push(self.result.to_string());
+
+        let mut line = String::new();
+        for word in words {
+            if !line.is_empty() && line.len() + 1 + word.len() >= LINE_LENGTH {
+                ret.push_str(&line);
+                ret.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&word);
+        }
+        ret.push_str(&line);
+        ret.push_str("\n\n");
+        ret
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Token {
+    TagOpen,
+    TagClose,
+    VariationOpen,
+    VariationClose,
+    Str(String),
+    // moves, move numbers, results and tag names
+    Symbol(String),
+    Period,
+    Star,
+    // numeric annotation glyphs such as $1 and suffixes such as !?
+    Nag,
+    Comment,
+}
+
+// Lexer splits the input into tokens, reading a line at a time
+struct Lexer<R> {
+    lines: std::io::Lines<R>,
+    chars: Vec<char>,
+    at: usize,
+    // number of the line being read, from 1
+    line: usize,
+    peeked: Option<Token>,
+    // the token most recently taken
+    last: Option<Token>,
+}
+
+impl<R: BufRead> Lexer<R> {
+    fn new(input: R) -> Lexer<R> {
+        Lexer {
+            lines: input.lines(),
+            chars: Vec::new(),
+            at: 0,
+            line: 0,
+            peeked: None,
+            last: None,
+        }
+    }
+
+    // peek_char returns the next character without taking it, reading lines as
+    // needed. Every line ends with a newline, so tokens never span lines.
+    fn peek_char(&mut self) -> Result<Option<char>, PgnError> {
+        while self.at >= self.chars.len() {
+            let Some(line) = self.lines.next() else {
+                return Ok(None);
+            };
+            let line = line.map_err(PgnError::Io)?;
+            self.line += 1;
+            self.at = 0;
+            // a % in the first column escapes the line, for other programs
+            self.chars = if line.starts_with('%') {
+                Vec::new()
+            } else {
+                line.chars().chain(['\n']).collect()
+            };
+        }
+        Ok(Some(self.chars[self.at]))
+    }
+
+    fn peek(&mut self) -> Result<Option<&Token>, PgnError> {
+        if self.peeked.is_none() {
+            self.peeked = self.lex()?;
+        }
+        Ok(self.peeked.as_ref())
+    }
+
+    fn token(&mut self) -> Result<Option<Token>, PgnError> {
+        let tok = match self.peeked.take() {
+            Some(tok) => Some(tok),
+            None => self.lex()?,
+        };
+        self.last = tok.clone();
+        Ok(tok)
+    }
+
+    fn lex(&mut self) -> Result<Option<Token>, PgnError> {
+        loop {
+            let Some(c) = self.peek_char()? else {
+                return Ok(None);
+            };
+            self.at += 1;
+            let tok = match c {
+                c if c.is_whitespace() => continue,
+                '[' => Token::TagOpen,
+                ']' => Token::TagClose,
+                '(' => Token::VariationOpen,
+                ')' => Token::VariationClose,
+                '.' => Token::Period,
+                '*' => Token::Star,
+                ';' => {
+                    self.at = self.chars.len();
+                    Token::Comment
+                }
+                '{' => {
+                    // brace comments may run over several lines
+                    loop {
+                        match self.peek_char()? {
+                            Some('}') => break,
+                            Some(_) => self.at += 1,
+                            None => return Err(PgnError::Syntax { line: self.line }),
+                        }
+                    }
+                    self.at += 1;
+                    Token::Comment
+                }
+                '"' => {
+                    let mut s = String::new();
+                    loop {
+                        let c = match self.chars[self.at] {
+                            '\\' if self.chars[self.at + 1] != '\n' => {
+                                self.at += 1;
+                                self.chars[self.at]
+                            }
+                            '"' => break,
+                            '\n' => return Err(PgnError::Syntax { line: self.line }),
+                            c => c,
+                        };
+                        s.push(c);
+                        self.at += 1;
+                    }
+                    self.at += 1;
+                    Token::Str(s)
+                }
+                '$' => {
+                    let digits = self.take_while(|c| c.is_ascii_digit());
+                    if digits.is_empty() {
+                        return Err(PgnError::Syntax { line: self.line });
+                    }
+                    Token::Nag
+                }
+                '!' | '?' => {
+                    self.take_while(|c| c == '!' || c == '?');
+                    Token::Nag
+                }
+                c if c.is_ascii_alphanumeric() => {
+                    let rest =
+                        self.take_while(|c| c.is_ascii_alphanumeric() || "_+#=:-/".contains(c));
+                    Token::Symbol(format!("{}{}", c, rest))
+                }
+                _ => return Err(PgnError::Syntax { line: self.line }),
+            };
+            return Ok(Some(tok));
+        }
+    }
+
+    // take_while takes the characters of the current line matching f
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> String {
+        let start = self.at;
+        while self.at < self.chars.len() && f(self.chars[self.at]) {
+            self.at += 1;
+        }
+        self.chars[start..self.at].iter().collect()
+    }
+}
+
+// Reader yields the games of a PGN stream in turn. A game with an error is
+// skipped after it is reported, so one bad game does not lose the rest.
+pub struct Reader<R> {
+    lexer: Lexer<R>,
+    done: bool,
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(input: R) -> Reader<R> {
+        Reader {
+            lexer: Lexer::new(input),
+            done: false,
+        }
+    }
+
+    fn read_game(&mut self) -> Result<Option<PgnGame>, PgnError> {
+        if self.lexer.peek()?.is_none() {
+            return Ok(None);
+        }
+
+        let mut tags = Vec::new();
+        let mut fen_line = 0;
+        while self.lexer.peek()? == Some(&Token::TagOpen) {
+            self.lexer.token()?;
+            let line = self.lexer.line;
+            match (
+                self.lexer.token()?,
+                self.lexer.token()?,
+                self.lexer.token()?,
+            ) {
+                (Some(Token::Symbol(name)), Some(Token::Str(value)), Some(Token::TagClose)) => {
+                    if name == "FEN" {
+                        fen_line = line;
+                    }
+                    tags.push((name, value))
+                }
+                _ => return Err(PgnError::Syntax { line }),
+            }
+        }
+
+        let start = match tags.iter().find(|(name, _)| name == "FEN") {
+            Some((_, fen)) => Position::from_fen(fen).map_err(|error| PgnError::Fen {
+                line: fen_line,
+                error,
+            })?,
+            None => Position::new(uci::STARTPOS),
+        };
+        let mut game = Game::new(start);
+
+        // The main line and then each variation being read, as the position
+        // before the last move and the current position. A variation replaces
+        // the last move of the line it is in, so starts before it.
+        let mut lines: Vec<(Option<Position>, Position)> = vec![(None, start)];
+        let result = loop {
+            // a missing result is forgiven when the next game starts
+            if lines.len() == 1 && self.lexer.peek()? == Some(&Token::TagOpen) {
+                break None;
+            }
+            let tok = self.lexer.token()?;
+            let line = self.lexer.line;
+            let in_variation = lines.len() > 1;
+            match tok {
+                None if in_variation => return Err(PgnError::Variation { line }),
+                None => break None,
+                Some(Token::Star) | Some(Token::Symbol(_)) if in_variation && self.ends_game() => {
+                    return Err(PgnError::Variation { line })
+                }
+                Some(Token::Star) => break Some(Outcome::Unknown),
+                Some(Token::Symbol(s)) => {
+                    if let Some(result) = outcome(&s) {
+                        break Some(result);
+                    }
+                    // move numbers, the periods after them being tokens of their own
+                    if s.bytes().all(|b| b.is_ascii_digit()) {
+                        continue;
+                    }
+                    let (before, pos) = lines.last_mut().expect("the main line is never closed");
+                    let mv = san::parse(pos, &s).map_err(|error| PgnError::Move {
+                        line,
+                        text: s.clone(),
+                        error,
+                    })?;
+                    *before = Some(*pos);
+                    *pos = pos.apply(mv);
+                    if !in_variation {
+                        game.play(mv);
+                    }
+                }
+                Some(Token::VariationOpen) => match lines.last().and_then(|&(before, _)| before) {
+                    Some(before) => lines.push((None, before)),
+                    None => return Err(PgnError::Variation { line }),
+                },
+                Some(Token::VariationClose) if in_variation => {
+                    lines.pop();
+                }
+                Some(Token::VariationClose) => return Err(PgnError::Variation { line }),
+                Some(Token::Period) | Some(Token::Nag) | Some(Token::Comment) => {}
+                Some(Token::TagOpen) | Some(Token::TagClose) | Some(Token::Str(_)) => {
+                    return Err(PgnError::Syntax { line })
+                }
+            }
+        };
+
+        let result = result
+            .or_else(|| {
+                tags.iter()
+                    .find(|(name, _)| name == "Result")
+                    .and_then(|(_, value)| outcome(value))
+            })
+            .unwrap_or(Outcome::Unknown);
+        Ok(Some(PgnGame { tags, game, result }))
+    }
+
+    // ends_game tells whether the token just taken is a game termination marker
+    fn ends_game(&self) -> bool {
+        match &self.lexer.last {
+            Some(Token::Star) => true,
+            Some(Token::Symbol(s)) => outcome(s).is_some(),
+            _ => false,
+        }
+    }
+
+    // skip_game takes tokens up to the end of the game being read, which is its
+    // result or, failing that, the start of the tags of the next game
+    fn skip_game(&mut self) -> Result<(), PgnError> {
+        loop {
+            let in_tags = self.lexer.last == Some(Token::TagClose);
+            match self.lexer.peek() {
+                Ok(Some(Token::TagOpen)) if !in_tags => return Ok(()),
+                Err(PgnError::Io(e)) => return Err(PgnError::Io(e)),
+                _ => {}
+            }
+            match self.lexer.token() {
+                Ok(None) => return Ok(()),
+                Ok(Some(_)) if self.ends_game() => return Ok(()),
+                Err(PgnError::Io(e)) => return Err(PgnError::Io(e)),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+    type Item = Result<PgnGame, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.read_game() {
+            Ok(Some(game)) => Some(Ok(game)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = matches!(e, PgnError::Io(_)) || self.skip_game().is_err();
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(text: &str) -> Vec<Result<PgnGame, PgnError>> {
+        Reader::new(text.as_bytes()).collect()
+    }
+
+    const OPERA: &str = r#"[Event "Paris"]
+[Site "Paris FRA"]
+[Date "1858.??.??"]
+[Round "?"]
+[White "Paul Morphy"]
+[Black "Duke Karl / Count Isouard"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 d6 3. d4 Bg4 4. dxe5 Bxf3 5. Qxf3 dxe5 6. Bc4 Nf6 7. Qb3 Qe7
+8. Nc3 c6 9. Bg5 b5 10. Nxb5 cxb5 11. Bxb5+ Nbd7 12. O-O-O Rd8 13. Rxd7 Rxd7
+14. Rd1 Qe6 15. Bxd7+ Nxd7 16. Qb8+ Nxb8 17. Rd8# 1-0
+
+"#;
+
+    #[test]
+    fn reads_games() {
+        let text = r#"% written by hand
+[Event "Casual"]
+[White "A \"quoted\" name"]
+
+1.e4 {best by test,
+as they say} 1...c5 $1 2.Nf3 (2.Nc3 Nc6 (2...d6 3.f4) 3.f4) 2...d6!? ; the Najdorf
+3.d4 cxd4 4.Nxd4 Nf6 5.Nc3 a6 *
+
+[Event "Endgame"]
+[SetUp "1"]
+[FEN "8/8/4k3/8/8/4K3/4P3/8 b - - 0 40"]
+
+40... Kd6 41. Kd4 (41. Kf4 Ke6) 1/2-1/2
+"#;
+        let games: Vec<PgnGame> = read(text).into_iter().map(Result::unwrap).collect();
+        assert_eq!(games.len(), 2);
+
+        assert_eq!(games[0].tag("White"), Some("A \"quoted\" name"));
+        assert_eq!(games[0].tag("Black"), None);
+        assert_eq!(games[0].game.moves().len(), 10);
+        assert_eq!(
+            games[0].game.position().to_fen(),
+            "rnbqkb1r/1p2pppp/p2p1n2/8/3NP3/2N5/PPP2PPP/R1BQKB1R w KQkq - 0 6"
+        );
+        assert_eq!(games[0].result, Outcome::Unknown);
+
+        assert_eq!(games[1].game.moves().len(), 2);
+        assert_eq!(
+            games[1].game.position().to_fen(),
+            "8/8/3k4/8/3K4/8/4P3/8 b - - 2 41"
+        );
+        assert_eq!(games[1].result, Outcome::Draw);
+
+        let games = read(OPERA);
+        let opera = games[0].as_ref().unwrap();
+        assert_eq!(opera.game.moves().len(), 33);
+        assert_eq!(
+            opera.game.status(),
+            GameStatus::Checkmate {
+                winner: enums::Colour::White
+            }
+        );
+        assert_eq!(opera.result, Outcome::WhiteWins);
+    }
+
+    #[test]
+    fn read_errors() {
+        let errors = |text: &str| -> Vec<String> {
+            read(text)
+                .into_iter()
+                .map(|game| match game {
+                    Ok(game) => format!("ok {}", game.game.moves().len()),
+                    Err(e) => e.to_string(),
+                })
+                .collect()
+        };
+
+        // the bad game is reported and the next one still read
+        assert_eq!(
+            errors("1. e4 e5 2. Ke3 Nc6 1-0\n\n1. d4 *\n"),
+            ["line 1: Ke3: no legal move matches", "ok 1"]
+        );
+        assert_eq!(
+            errors("[Event \"x\"]\n[Result]\n1. e4 *\n[Event \"y\"]\n1. d4 *"),
+            ["line 2: syntax error", "ok 1"]
+        );
+        // without a result, the next tags start the next game
+        assert_eq!(
+            errors("[Event \"x\"]\n1. e4 Nf3\n[Event \"y\"]\n1. d4 d5 *"),
+            ["line 2: Nf3: no legal move matches", "ok 2"]
+        );
+        assert_eq!(errors("1. e4 e5 2. Nf3 Nc6 3. Bb5"), ["ok 5"]);
+        assert_eq!(
+            errors("[FEN \"8/8/8 w - - 0 1\"]\n1. e4 *"),
+            ["line 1: bad FEN tag: invalid Placement field at byte 5"]
+        );
+
+        assert_eq!(errors("(1. d4) 1. e4 *"), ["line 1: misplaced variation"]);
+        assert_eq!(errors("1. e4 (1. d4 *"), ["line 1: misplaced variation"]);
+        assert_eq!(errors("1. e4 ) *"), ["line 1: misplaced variation"]);
+        assert_eq!(errors("1. e4 {unclosed\n"), ["line 1: syntax error"]);
+        assert_eq!(
+            errors("1. e4 & *\n1. d4 *"),
+            ["line 1: syntax error", "ok 1"]
+        );
+        assert!(errors("").is_empty());
+    }
+
+    #[test]
+    fn writes_games() {
+        let games = read(OPERA);
+        let opera = games[0].as_ref().unwrap();
+        assert_eq!(opera.to_pgn(), OPERA);
+
+        // a game without tags, from a position with black to move
+        let mut game = Game::new(Position::new("4k3/8/8/8/8/8/4P3/4K3 b - - 0 60"));
+        for mv in ["e8d7", "e2e4"] {
+            game.play(game.position().parse_uci_move(mv).unwrap());
+        }
+        let record = PgnGame::new(game);
+        let pgn = record.to_pgn();
+        assert!(pgn.ends_with(
+            "[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 60\"]\n\n60... Kd7 61. e4 *\n\n"
+        ));
+        assert!(pgn.starts_with("[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n"));
+
+        let read_back = read(&pgn);
+        let read_back = read_back[0].as_ref().unwrap();
+        assert_eq!(read_back.game.keys(), record.game.keys());
+        assert_eq!(read_back.tag("Result"), Some("*"));
+    }
+}
@@ -1,50 +1,115 @@
-use crate::aliases::{Bitboard, Move, Square};
-use crate::masks::Lookup;
-use crate::{enums, masks, tables, utils};
-
-// Using From-To based move encoding
-//
-//      0    |   0   |    0    |    0    | 000000 | 000000
-//  ---------|-------|---------|---------|--------|--------
-//  promotion|capture|special 1|special 0|  from  |   to
-//
-// https://www.chessprogramming.org/Encoding_Moves
-
-pub fn move_get_to(mov: Move) -> u8 {
-    (mov & 0x3f) as u8
+use crate::movelist::MoveList;
+use crate::types::{
+    Bitboard, Move, Square, FLAG_CAPTURE, FLAG_CAPTURE_PROMOTE_KNIGHT, FLAG_DOUBLE_PAWN_PUSH,
+    FLAG_EP_CAPTURE, FLAG_KING_CASTLE, FLAG_PROMOTE_KNIGHT, FLAG_QUEEN_CASTLE, FLAG_QUIET_MOVE,
+};
+use crate::zobrist::KEYS;
+use crate::{attacks, enums, utils};
+
+const WKING_CASTLE_RIGHTS: u8 = 1 << 0;
+const WQUEEN_CASTLE_RIGHTS: u8 = 1 << 1;
+const BKING_CASTLE_RIGHTS: u8 = 1 << 2;
+const BQUEEN_CASTLE_RIGHTS: u8 = 1 << 3;
+
+const LIGHT_SQUARES: Bitboard = Bitboard(0x55aa55aa55aa55aa);
+
+// Castling rights lost when a move touches (leaves from or lands on) a square
+const CASTLING_SQUARES: [(Square, u8); 6] = [
+    (Square::A1, WQUEEN_CASTLE_RIGHTS),
+    (Square::E1, WKING_CASTLE_RIGHTS | WQUEEN_CASTLE_RIGHTS),
+    (Square::H1, WKING_CASTLE_RIGHTS),
+    (Square::A8, BQUEEN_CASTLE_RIGHTS),
+    (Square::E8, BKING_CASTLE_RIGHTS | BQUEEN_CASTLE_RIGHTS),
+    (Square::H8, BKING_CASTLE_RIGHTS),
+];
+
+// Subsets of the legal moves, for searches which look at them in stages
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum GenKind {
+    // every legal move
+    All,
+    // captures, including en passant, and promotions
+    Captures,
+    // moves which neither capture nor promote, including castling
+    Quiets,
+    // every legal move when in check, nothing otherwise
+    Evasions,
+    // quiet moves which give check
+    QuietChecks,
 }
 
-pub fn move_get_from(mov: Move) -> u8 {
-    ((mov >> 6) & 0x3f) as u8
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FenField {
+    Placement,
+    Side,
+    Castling,
+    EnPassant,
+    Halfmove,
+    Fullmove,
 }
 
-pub fn move_get_code(mov: Move) -> u8 {
-    ((mov >> 12) & 0xf) as u8
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FenError {
+    // A required field is absent
+    Missing { field: FenField },
+    // A field could not be parsed, offset is the byte in the fen where it goes wrong
+    Invalid { field: FenField, offset: usize },
+    // Something follows the fullmove number
+    Trailing { offset: usize },
+    KingCount { colour: enums::Colour, kings: u32 },
+    PawnOnBackRank { square: Square },
+    // A castling right is given without the king and rook in place
+    CastlingWithoutPieces { right: char },
+    ImpossibleEnPassant { square: Square },
+    // The king of the side which just moved is attacked, so could be taken
+    OpponentInCheck,
 }
 
-fn make_move(from: Square, to: Square, special: u8) -> Move {
-    (to as Move) | ((from as Move) << 6) | ((special as Move) << 12)
+impl std::fmt::Display for FenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FenError::Missing { field } => write!(f, "missing {:?} field", field),
+            FenError::Invalid { field, offset } => {
+                write!(f, "invalid {:?} field at byte {}", field, offset)
+            }
+            FenError::Trailing { offset } => write!(f, "unexpected field at byte {}", offset),
+            FenError::KingCount { colour, kings } => {
+                write!(f, "{:?} has {} kings, expected 1", colour, kings)
+            }
+            FenError::PawnOnBackRank { square } => {
+                write!(f, "pawn on back rank at {}", square)
+            }
+            FenError::CastlingWithoutPieces { right } => {
+                write!(f, "castling right {} without king and rook in place", right)
+            }
+            FenError::ImpossibleEnPassant { square } => {
+                write!(f, "impossible en passant square {}", square)
+            }
+            FenError::OpponentInCheck => write!(f, "side not to move is in check"),
+        }
+    }
 }
 
-const FLAG_QUIET_MOVE: u8 = 0;
-const FLAG_DOUBLE_PAWN_PUSH: u8 = 1;
-const FLAG_KING_CASTLE: u8 = 2;
-const FLAG_QUEEN_CASTLE: u8 = 3;
-const FLAG_CAPTURE: u8 = 4;
-const FLAG_EP_CAPTURE: u8 = 5;
-const FLAG_PROMOTE_KNIGHT: u8 = 8;
-const FLAG_PROMOTE_BISHOP: u8 = 9;
-const FLAG_PROMOTE_ROOK: u8 = 10;
-const FLAG_PROMOTE_QUEEN: u8 = 11;
-const FLAG_CAPTURE_PROMOTE_KNIGHT: u8 = 12;
-const FLAG_CAPTURE_PROMOTE_BISHOP: u8 = 13;
-const FLAG_CAPTURE_PROMOTE_ROOK: u8 = 14;
-const FLAG_CAPTURE_PROMOTE_QUEEN: u8 = 15;
+impl std::error::Error for FenError {}
 
-const WKING_CASTLE_RIGHTS: u8 = 1 << 0;
-const WQUEEN_CASTLE_RIGHTS: u8 = 1 << 1;
-const BKING_CASTLE_RIGHTS: u8 = 1 << 2;
-const BQUEEN_CASTLE_RIGHTS: u8 = 1 << 3;
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum UciMoveError {
+    // Not two squares and an optional promotion letter, as in e2e4 or e7e8q
+    Syntax,
+    // Well formed, but not a legal move in the position
+    Illegal,
+}
+
+impl std::fmt::Display for UciMoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            UciMoveError::Syntax => write!(f, "expected a move such as e2e4 or e7e8q"),
+            UciMoveError::Illegal => write!(f, "not a legal move"),
+        }
+    }
+}
+
+impl std::error::Error for UciMoveError {}
 
 #[derive(Copy, Clone)]
 pub struct Position {
@@ -52,77 +117,323 @@ pub struct Position {
     side_bitboards: [Bitboard; 2],
     all_bitboard: Bitboard,
     side: enums::Colour,
-    ep_target: Square,
+    ep_target: Option<Square>,
 
     // castling rights: qkQK
     castling: u8,
+
+    // plies since the last capture or pawn move, and the move number, which
+    // starts at 1 and increases after each black move
+    halfmove: u16,
+    fullmove: u16,
+
+    // Zobrist key, kept up to date by apply
+    key: u64,
 }
 
 impl Position {
+    // new parses a fen known to be valid, panicking otherwise. Untrusted input
+    // should go through from_fen.
     pub fn new(fen: &str) -> Position {
+        match Position::from_fen(fen) {
+            Ok(pos) => pos,
+            Err(e) => panic!("bad fen provided: {}", e),
+        }
+    }
+
+    pub fn from_fen(fen: &str) -> Result<Position, FenError> {
         let mut ret = Position {
-            bitboards: [[0u64; 6]; 2],
-            side_bitboards: [0u64; 2],
-            all_bitboard: 0,
+            bitboards: [[Bitboard::EMPTY; 6]; 2],
+            side_bitboards: [Bitboard::EMPTY; 2],
+            all_bitboard: Bitboard::EMPTY,
             side: enums::Colour::White,
-            ep_target: enums::Square::Null as Square,
+            ep_target: None,
             castling: 0,
+            halfmove: 0,
+            fullmove: 1,
+            key: 0,
         };
-        let mut tokens = fen.split(' ');
-
-        let board_token = tokens
-            .next()
-            .expect("fen piece placement data not provided");
-        for (rk, line) in (0..8).zip(board_token.split('/').rev()) {
-            let mut fl = 0;
-            for c in line.chars() {
-                let cp = utils::ascii_colour_piece(c);
-                match cp {
-                    Some((colour, piece)) => {
-                        let sq = 8 * rk + fl;
-                        ret.bitboards[colour as usize][piece as usize] |= 1 << sq;
-                        fl += 1;
-                    }
-                    None => {
-                        fl += c
-                            .to_digit(10)
-                            .expect("expected number to describe empty squares");
+
+        // Split on spaces while remembering where each field starts, so errors
+        // can point into the string
+        let mut tokens = fen
+            .split(' ')
+            .scan(0, |offset, token| {
+                let start = *offset;
+                *offset += token.len() + 1;
+                Some((start, token))
+            })
+            .filter(|(_, token)| !token.is_empty());
+
+        let (board_offset, board_token) = tokens.next().ok_or(FenError::Missing {
+            field: FenField::Placement,
+        })?;
+        let mut rk: u8 = 8;
+        let mut offset = board_offset;
+        for line in board_token.split('/') {
+            if rk == 0 {
+                return Err(FenError::Invalid {
+                    field: FenField::Placement,
+                    offset,
+                });
+            }
+            rk -= 1;
+            let mut fl: u8 = 0;
+            for (i, c) in line.char_indices() {
+                let bad_char = FenError::Invalid {
+                    field: FenField::Placement,
+                    offset: offset + i,
+                };
+                let width = match utils::ascii_colour_piece(c) {
+                    Some((colour, piece)) if fl < 8 => {
+                        let sq = Square::from_coords(rk, fl);
+                        ret.bitboards[colour as usize][piece as usize] |= sq.bb();
+                        1
                     }
+                    Some(_) => return Err(bad_char),
+                    None => match c.to_digit(10) {
+                        Some(n @ 1..=8) => n as u8,
+                        _ => return Err(bad_char),
+                    },
+                };
+                fl += width;
+                if fl > 8 {
+                    return Err(bad_char);
                 }
             }
+            if fl != 8 {
+                return Err(FenError::Invalid {
+                    field: FenField::Placement,
+                    offset: offset + line.len(),
+                });
+            }
+            offset += line.len() + 1;
+        }
+        if rk != 0 {
+            return Err(FenError::Invalid {
+                field: FenField::Placement,
+                offset: board_offset + board_token.len(),
+            });
         }
 
-        let side_token = tokens.next().expect("fen active color not provided");
+        let (side_offset, side_token) = tokens.next().ok_or(FenError::Missing {
+            field: FenField::Side,
+        })?;
         match side_token {
             "w" => ret.side = enums::Colour::White,
             "b" => ret.side = enums::Colour::Black,
-            _ => panic!("bad fen active colour provided, expected 'w' or 'b'"),
+            _ => {
+                return Err(FenError::Invalid {
+                    field: FenField::Side,
+                    offset: side_offset,
+                })
+            }
         }
 
-        let castling_token = tokens.next().expect("fen castling rights not provided");
-        for (i, c) in "KQkq".chars().enumerate() {
-            if castling_token.contains(c) {
-                ret.castling |= 1 << i;
+        let (castling_offset, castling_token) = tokens.next().ok_or(FenError::Missing {
+            field: FenField::Castling,
+        })?;
+        if castling_token != "-" {
+            for (i, c) in castling_token.char_indices() {
+                match "KQkq".find(c) {
+                    Some(bit) if ret.castling & (1 << bit) == 0 => ret.castling |= 1 << bit,
+                    _ => {
+                        return Err(FenError::Invalid {
+                            field: FenField::Castling,
+                            offset: castling_offset + i,
+                        })
+                    }
+                }
             }
         }
 
-        let ep_target_token = tokens.next().expect("fen en passant target not provided");
-        ret.ep_target = utils::string_square(ep_target_token);
+        let (ep_offset, ep_target_token) = tokens.next().ok_or(FenError::Missing {
+            field: FenField::EnPassant,
+        })?;
+        ret.ep_target = match ep_target_token {
+            "-" => None,
+            token => Some(token.parse().map_err(|_| FenError::Invalid {
+                field: FenField::EnPassant,
+                offset: ep_offset,
+            })?),
+        };
+
+        // the move counters are often left out, in which case the defaults stand
+        if let Some((offset, halfmove_token)) = tokens.next() {
+            ret.halfmove = halfmove_token.parse().map_err(|_| FenError::Invalid {
+                field: FenField::Halfmove,
+                offset,
+            })?;
+        }
+        if let Some((offset, fullmove_token)) = tokens.next() {
+            ret.fullmove = fullmove_token.parse().map_err(|_| FenError::Invalid {
+                field: FenField::Fullmove,
+                offset,
+            })?;
+        }
+        if let Some((offset, _)) = tokens.next() {
+            return Err(FenError::Trailing { offset });
+        }
 
         for (i, side_bb) in ret.side_bitboards.iter_mut().enumerate() {
-            *side_bb = ret.bitboards[i].iter().fold(0, |acc, bb| acc | bb);
+            *side_bb = ret.bitboards[i]
+                .iter()
+                .fold(Bitboard::EMPTY, |acc, &bb| acc | bb);
         }
 
         ret.all_bitboard = ret.side_bitboards[0] | ret.side_bitboards[1];
 
-        ret
+        ret.validate()?;
+        ret.key = ret.hash();
+        Ok(ret)
+    }
+
+    // validate rejects well-formed fens describing positions the move generator
+    // cannot handle
+    fn validate(&self) -> Result<(), FenError> {
+        for colour in enums::Colour::values() {
+            let kings = self.bitboards[colour as usize][enums::Piece::King as usize].count();
+            if kings != 1 {
+                return Err(FenError::KingCount { colour, kings });
+            }
+        }
+
+        let them = self.side.other();
+        if self.is_square_attacked(self.king_square(them), self.side) {
+            return Err(FenError::OpponentInCheck);
+        }
+
+        let pawns = self.bitboards[0][enums::Piece::Pawn as usize]
+            | self.bitboards[1][enums::Piece::Pawn as usize];
+        let back_ranks = Bitboard(0xff000000000000ff);
+        if let Some(square) = (pawns & back_ranks).first() {
+            return Err(FenError::PawnOnBackRank { square });
+        }
+
+        // each right needs the king and the rook on their original squares
+        let rights = [
+            (
+                WKING_CASTLE_RIGHTS,
+                enums::Colour::White,
+                Square::E1,
+                Square::H1,
+            ),
+            (
+                WQUEEN_CASTLE_RIGHTS,
+                enums::Colour::White,
+                Square::E1,
+                Square::A1,
+            ),
+            (
+                BKING_CASTLE_RIGHTS,
+                enums::Colour::Black,
+                Square::E8,
+                Square::H8,
+            ),
+            (
+                BQUEEN_CASTLE_RIGHTS,
+                enums::Colour::Black,
+                Square::E8,
+                Square::A8,
+            ),
+        ];
+        for (i, (right, colour, king, rook)) in rights.into_iter().enumerate() {
+            let pieces = self.bitboards[colour as usize];
+            if self.castling & right != 0
+                && (!pieces[enums::Piece::King as usize].contains(king)
+                    || !pieces[enums::Piece::Rook as usize].contains(rook))
+            {
+                return Err(FenError::CastlingWithoutPieces {
+                    right: "KQkq".as_bytes()[i] as char,
+                });
+            }
+        }
+
+        // the en passant target is the empty square just passed by a pawn of the
+        // side which moved last. The rank is checked first, so the squares either
+        // side of the target are on the board.
+        if let Some(sq) = self.ep_target {
+            let (target_rank, forward) = match self.side {
+                enums::Colour::White => (5, -8),
+                enums::Colour::Black => (2, 8),
+            };
+            let them = self.side.other();
+            if sq.rank() != target_rank
+                || !(self.all_bitboard & (sq.bb() | sq.offset(-forward).bb())).is_empty()
+                || !self.bitboards[them as usize][enums::Piece::Pawn as usize]
+                    .contains(sq.offset(forward))
+            {
+                return Err(FenError::ImpossibleEnPassant { square: sq });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn to_fen(self) -> String {
+        let mut board = String::new();
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                match self.piece_at(Square::from_coords(rank, file)) {
+                    Some((colour, piece)) => {
+                        if empty != 0 {
+                            board.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        board.push(utils::colour_piece_ascii(colour, piece));
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty != 0 {
+                board.push_str(&empty.to_string());
+            }
+            if rank != 0 {
+                board.push('/');
+            }
+        }
+
+        let side_string = match self.side {
+            enums::Colour::White => "w",
+            enums::Colour::Black => "b",
+        };
+
+        format!(
+            "{} {} {} {} {} {}",
+            board,
+            side_string,
+            self.castling_string(),
+            self.ep_string(),
+            self.halfmove,
+            self.fullmove
+        )
+    }
+
+    fn castling_string(&self) -> String {
+        let ret: String = "KQkq"
+            .chars()
+            .enumerate()
+            .filter(|&(i, _)| (self.castling >> i) & 1 != 0)
+            .map(|(_, c)| c)
+            .collect();
+        if ret.is_empty() {
+            String::from("-")
+        } else {
+            ret
+        }
+    }
+
+    fn ep_string(&self) -> String {
+        self.ep_target
+            .map_or(String::from("-"), |sq| sq.to_string())
     }
 
     fn square_repr(&self, sq: Square) -> char {
         for colour in enums::Colour::values() {
             for piece in enums::Piece::values() {
                 let bitboard = self.bitboards[colour as usize][piece as usize];
-                if (bitboard >> sq) & 1 == 1 {
+                if bitboard.contains(sq) {
                     match "♘♗♖♕♙♔♞♝♜♛♟♚"
                         .chars()
                         .nth(6 * colour as usize + piece as usize)
@@ -142,26 +453,19 @@ impl Position {
             enums::Colour::Black => "b",
         };
 
-        let mut castling_string = String::new();
-        for (i, c) in "KQkq".chars().enumerate() {
-            if (self.castling >> i) & 1 != 0 {
-                castling_string.push(c);
-            }
-        }
-        if castling_string.is_empty() {
-            castling_string = String::from("-");
-        }
-
         let mut ret = format!(
             "{} cs:{} ep:{}\n",
             side_string,
-            castling_string,
-            utils::square_string(self.ep_target)
+            self.castling_string(),
+            self.ep_string()
         );
         for rank in (0..8).rev() {
-            ret.push_str(&*format!("{} ", rank + 1));
+            ret.push_str(&format!("{} ", rank + 1));
             for file in 0..8 {
-                ret.push_str(&*format!("{}", self.square_repr(8 * rank + file)));
+                ret.push_str(&format!(
+                    "{}",
+                    self.square_repr(Square::from_coords(rank, file))
+                ));
                 if file != 7 {
                     ret.push(' ');
                 }
@@ -172,217 +476,1109 @@ impl Position {
         ret
     }
 
-    pub fn generate_pseudo_legal(&self, m: &masks::Lookup, t: &tables::Lookup) -> Vec<Move> {
-        let pieces_bb = match self.side {
-            enums::Colour::White => self.bitboards[0],
-            enums::Colour::Black => self.bitboards[1],
+    pub fn piece_at(&self, sq: Square) -> Option<(enums::Colour, enums::Piece)> {
+        if !self.all_bitboard.contains(sq) {
+            return None;
+        }
+        for colour in enums::Colour::values() {
+            for piece in enums::Piece::values() {
+                if self.bitboards[colour as usize][piece as usize].contains(sq) {
+                    return Some((colour, piece));
+                }
+            }
+        }
+        None
+    }
+
+    pub fn side(&self) -> enums::Colour {
+        self.side
+    }
+
+    pub fn pieces(&self, colour: enums::Colour, piece: enums::Piece) -> Bitboard {
+        self.bitboards[colour as usize][piece as usize]
+    }
+
+    pub fn key(&self) -> u64 {
+        self.key
+    }
+
+    // halfmove returns the plies since the last capture or pawn move
+    pub fn halfmove(&self) -> u16 {
+        self.halfmove
+    }
+
+    pub fn fullmove(&self) -> u16 {
+        self.fullmove
+    }
+
+    // has_insufficient_material tells whether neither side can ever mate: bare
+    // kings, a single minor piece, or any bishops all on squares of one colour
+    pub fn has_insufficient_material(&self) -> bool {
+        let [white, black] = self.bitboards;
+        let pawns_and_majors = |side: [Bitboard; 6]| {
+            side[enums::Piece::Pawn as usize]
+                | side[enums::Piece::Rook as usize]
+                | side[enums::Piece::Queen as usize]
         };
+        if !(pawns_and_majors(white) | pawns_and_majors(black)).is_empty() {
+            return false;
+        }
+        let knights = white[enums::Piece::Knight as usize] | black[enums::Piece::Knight as usize];
+        let bishops = white[enums::Piece::Bishop as usize] | black[enums::Piece::Bishop as usize];
+        match knights.count() {
+            0 => (bishops & LIGHT_SQUARES).is_empty() || (bishops & !LIGHT_SQUARES).is_empty(),
+            1 => bishops.is_empty(),
+            _ => false,
+        }
+    }
+
+    // hash computes the Zobrist key from scratch, it always agrees with key
+    pub fn hash(&self) -> u64 {
+        let mut ret = 0;
+        for colour in enums::Colour::values() {
+            for piece in enums::Piece::values() {
+                for sq in self.bitboards[colour as usize][piece as usize] {
+                    ret ^= KEYS.pieces[colour as usize][piece as usize][sq.index()];
+                }
+            }
+        }
+        if self.side == enums::Colour::Black {
+            ret ^= KEYS.side;
+        }
+        ret ^= KEYS.castling[self.castling as usize];
+        ret ^ self.ep_key()
+    }
+
+    // can_take_en_passant tells whether a pawn of colour by attacks the en
+    // passant target sq. Only such targets are hashed, so that positions which
+    // differ in nothing else hash alike, as repetition detection needs.
+    fn can_take_en_passant(&self, sq: Square, by: enums::Colour) -> bool {
+        let mover = by.other();
+        !(attacks::pawn_attacks(mover, sq)
+            & self.bitboards[by as usize][enums::Piece::Pawn as usize])
+            .is_empty()
+    }
 
-        let mut moves: Vec<Move> = Vec::new();
+    // ep_key hashes the en passant target only when the side to move has a pawn
+    // to take on it, as otherwise the position is the same as without it
+    fn ep_key(&self) -> u64 {
+        match self.ep_target {
+            Some(sq) if self.can_take_en_passant(sq, self.side) => KEYS.ep_file[sq.file() as usize],
+            _ => 0,
+        }
+    }
+
+    // toggle adds or removes a piece on sq, keeping the aggregate bitboards and
+    // the key in sync
+    fn toggle(&mut self, colour: enums::Colour, piece: enums::Piece, sq: Square) {
+        let bb = sq.bb();
+        self.key ^= KEYS.pieces[colour as usize][piece as usize][sq.index()];
+        self.bitboards[colour as usize][piece as usize] ^= bb;
+        self.side_bitboards[colour as usize] ^= bb;
+        self.all_bitboard ^= bb;
+    }
+
+    // apply returns the position reached by playing mv (copy-make). mv is assumed
+    // to be a pseudo-legal move generated from this position.
+    pub fn apply(&self, mv: Move) -> Position {
+        let mut ret = *self;
+        let (from, to, code) = (mv.from(), mv.to(), mv.flag());
+        let us = self.side;
+        let them = us.other();
+        let (_, piece) = self
+            .piece_at(from)
+            .expect("no piece on the from square of the move");
 
+        // remove the captured piece, which for en passant sits behind the target
+        if code == FLAG_EP_CAPTURE {
+            let captured = match us {
+                enums::Colour::White => to.offset(-8),
+                enums::Colour::Black => to.offset(8),
+            };
+            ret.toggle(them, enums::Piece::Pawn, captured);
+        } else if let Some((_, captured)) = self.piece_at(to) {
+            ret.toggle(them, captured, to);
+        }
+
+        // move the piece, replacing it on promotion
+        let placed = mv.promotion().unwrap_or(piece);
+        ret.toggle(us, piece, from);
+        ret.toggle(us, placed, to);
+
+        // castling also moves the rook, to the square the king passed over
+        match code {
+            FLAG_KING_CASTLE => {
+                ret.toggle(us, enums::Piece::Rook, to.offset(1));
+                ret.toggle(us, enums::Piece::Rook, to.offset(-1));
+            }
+            FLAG_QUEEN_CASTLE => {
+                ret.toggle(us, enums::Piece::Rook, to.offset(-2));
+                ret.toggle(us, enums::Piece::Rook, to.offset(1));
+            }
+            _ => {}
+        }
+
+        ret.key ^= self.ep_key() ^ KEYS.castling[self.castling as usize];
+
+        ret.ep_target = if code == FLAG_DOUBLE_PAWN_PUSH {
+            Some(Square::new(((from.index() + to.index()) / 2) as u8))
+        } else {
+            None
+        };
+
+        for (sq, rights) in CASTLING_SQUARES {
+            if from == sq || to == sq {
+                ret.castling &= !rights;
+            }
+        }
+
+        // the side changes first, as the new en passant key depends on it
+        ret.side = them;
+        ret.key ^= ret.ep_key() ^ KEYS.castling[ret.castling as usize] ^ KEYS.side;
+
+        // the counters stop at their largest value rather than wrapping, as a
+        // fen may start them anywhere
+        if piece == enums::Piece::Pawn || self.all_bitboard.contains(to) {
+            ret.halfmove = 0;
+        } else {
+            ret.halfmove = ret.halfmove.saturating_add(1);
+        }
+        if us == enums::Colour::Black {
+            ret.fullmove = ret.fullmove.saturating_add(1);
+        }
+        ret
+    }
+
+    // generate_pseudo_legal adds the moves of the side to move to list, leaving
+    // in those which expose its king to capture
+    pub fn generate_pseudo_legal(&self, list: &mut MoveList) {
+        self.gen_pseudo_legal(!self.side_bitboards[self.side as usize], list);
+    }
+
+    // gen_pseudo_legal adds the pseudo-legal moves landing on targets. Pawn
+    // moves are not narrowed down, as promotions and en passant do not follow
+    // the occupancy of their destination.
+    fn gen_pseudo_legal(&self, targets: Bitboard, list: &mut MoveList) {
+        let pieces_bb = self.bitboards[self.side as usize];
         for piece in enums::Piece::values() {
-            let piece_bb = pieces_bb[piece as usize];
-            for sq in bb_squares(piece_bb) {
-                let mut pos_moves = match piece {
-                    enums::Piece::King => self.gen_king_moves(sq, m),
-                    enums::Piece::Queen => self.gen_queen_moves(sq, m, t),
-                    enums::Piece::Rook => self.gen_rook_moves(sq, m, t),
-                    enums::Piece::Bishop => self.gen_bishop_moves(sq, m, t),
-                    enums::Piece::Knight => self.gen_knight_moves(sq, m),
-                    enums::Piece::Pawn => self.gen_pawn_moves(sq, m),
-                };
-                moves.append(&mut pos_moves);
+            for sq in pieces_bb[piece as usize] {
+                match piece {
+                    enums::Piece::King => self.gen_king_moves(sq, targets, list),
+                    enums::Piece::Queen => self.gen_queen_moves(sq, targets, list),
+                    enums::Piece::Rook => self.gen_rook_moves(sq, targets, list),
+                    enums::Piece::Bishop => self.gen_bishop_moves(sq, targets, list),
+                    enums::Piece::Knight => self.gen_knight_moves(sq, targets, list),
+                    enums::Piece::Pawn => self.gen_pawn_moves(sq, list),
+                }
+            }
+        }
+    }
+
+    // attackers_to returns the pieces of both sides attacking sq, with sliders
+    // blocked by the pieces in occ
+    pub fn attackers_to(&self, sq: Square, occ: Bitboard) -> Bitboard {
+        let [white, black] = self.bitboards;
+        let diagonal = white[enums::Piece::Bishop as usize]
+            | white[enums::Piece::Queen as usize]
+            | black[enums::Piece::Bishop as usize]
+            | black[enums::Piece::Queen as usize];
+        let straight = white[enums::Piece::Rook as usize]
+            | white[enums::Piece::Queen as usize]
+            | black[enums::Piece::Rook as usize]
+            | black[enums::Piece::Queen as usize];
+
+        // a pawn of one colour attacks sq exactly when a pawn of the other colour
+        // on sq would attack it
+        (attacks::pawn_attacks(enums::Colour::Black, sq) & white[enums::Piece::Pawn as usize])
+            | (attacks::pawn_attacks(enums::Colour::White, sq) & black[enums::Piece::Pawn as usize])
+            | (attacks::knight_attacks(sq)
+                & (white[enums::Piece::Knight as usize] | black[enums::Piece::Knight as usize]))
+            | (attacks::king_attacks(sq)
+                & (white[enums::Piece::King as usize] | black[enums::Piece::King as usize]))
+            | (attacks::bishop_attacks(sq, occ) & diagonal)
+            | (attacks::rook_attacks(sq, occ) & straight)
+    }
+
+    // is_square_attacked tells whether any piece of colour by attacks sq
+    pub fn is_square_attacked(&self, sq: Square, by: enums::Colour) -> bool {
+        !(self.attackers_to(sq, self.all_bitboard) & self.side_bitboards[by as usize]).is_empty()
+    }
+
+    // in_check tells whether the side to move is in check
+    pub fn in_check(&self) -> bool {
+        !self.checkers().is_empty()
+    }
+
+    // checkers returns the pieces giving check to the side to move
+    pub fn checkers(&self) -> Bitboard {
+        self.attackers_to(self.king_square(self.side), self.all_bitboard)
+            & self.side_bitboards[self.side.other() as usize]
+    }
+
+    // pinned returns the pieces of colour which may not leave the line between
+    // their king and an enemy slider
+    pub fn pinned(&self, colour: enums::Colour) -> Bitboard {
+        self.pins(colour).0
+    }
+
+    // pins returns the pinned pieces of colour, and for each the squares it may
+    // still move to: the ray to the pinner, including capturing it. Enemy
+    // sliders which would see the king through exactly one of our pieces pin
+    // that piece.
+    fn pins(&self, colour: enums::Colour) -> (Bitboard, [Bitboard; 64]) {
+        let king = self.king_square(colour);
+        let theirs = self.side_bitboards[colour.other() as usize];
+        let their_pieces = self.bitboards[colour.other() as usize];
+        let snipers = (attacks::bishop_attacks(king, theirs)
+            & (their_pieces[enums::Piece::Bishop as usize]
+                | their_pieces[enums::Piece::Queen as usize]))
+            | (attacks::rook_attacks(king, theirs)
+                & (their_pieces[enums::Piece::Rook as usize]
+                    | their_pieces[enums::Piece::Queen as usize]));
+        let mut pinned = Bitboard::EMPTY;
+        let mut pin_rays = [Bitboard::EMPTY; 64];
+        for sniper in snipers {
+            let ray = between(king, sniper);
+            let blockers = ray & self.all_bitboard;
+            if let (1, Some(blocker)) = (blockers.count(), blockers.first()) {
+                if self.side_bitboards[colour as usize].contains(blocker) {
+                    pinned |= blockers;
+                    pin_rays[blocker.index()] = ray | sniper.bb();
+                }
+            }
+        }
+        (pinned, pin_rays)
+    }
+
+    fn king_square(&self, colour: enums::Colour) -> Square {
+        self.bitboards[colour as usize][enums::Piece::King as usize]
+            .first()
+            .expect("no king on the board")
+    }
+
+    pub fn generate_legal(&self) -> MoveList {
+        let mut list = MoveList::new();
+        self.generate(GenKind::All, &mut list);
+        list
+    }
+
+    // parse_uci_move reads a move in the long algebraic notation of UCI and finds
+    // it among the legal moves, which gives it its flag
+    // See: https://www.chessprogramming.org/Algebraic_Chess_Notation#UCI
+    pub fn parse_uci_move(&self, text: &str) -> Result<Move, UciMoveError> {
+        let square = |range| {
+            text.get(range)
+                .and_then(|s: &str| s.parse::<Square>().ok())
+                .ok_or(UciMoveError::Syntax)
+        };
+        let (from, to) = (square(0..2)?, square(2..4)?);
+        let promotion = match &text[4..] {
+            "" => None,
+            "n" => Some(enums::Piece::Knight),
+            "b" => Some(enums::Piece::Bishop),
+            "r" => Some(enums::Piece::Rook),
+            "q" => Some(enums::Piece::Queen),
+            _ => return Err(UciMoveError::Syntax),
+        };
+        self.generate_legal()
+            .into_iter()
+            .find(|mv| mv.from() == from && mv.to() == to && mv.promotion() == promotion)
+            .ok_or(UciMoveError::Illegal)
+    }
+
+    // generate adds the legal moves of the given kind to list
+    pub fn generate(&self, kind: GenKind, list: &mut MoveList) {
+        let checkers = self.checkers();
+        if kind == GenKind::Evasions && checkers.is_empty() {
+            return;
+        }
+
+        let us = self.side;
+        let them = us.other();
+        let king = self.king_square(us);
+        let theirs = self.side_bitboards[them as usize];
+
+        // Squares a non-king move has to land on: anywhere when not in check, the
+        // checker or a square blocking it in single check, nowhere in double check
+        let check_mask = match (checkers.count(), checkers.first()) {
+            (0, _) => Bitboard::FULL,
+            (1, Some(checker)) => checkers | between(king, checker),
+            _ => Bitboard::EMPTY,
+        };
+
+        // pinned pieces may only move along the ray to the pinner
+        let (pinned, pin_rays) = self.pins(us);
+
+        // The king may not stand on an attacked square, including squares behind it
+        // on the line of a checking slider, so it is lifted from the board first
+        let without_king = self.all_bitboard ^ king.bb();
+        let attacked = |sq: Square| !(self.attackers_to(sq, without_king) & theirs).is_empty();
+
+        let targets = match kind {
+            GenKind::Captures => theirs,
+            GenKind::Quiets | GenKind::QuietChecks => !self.all_bitboard,
+            GenKind::All | GenKind::Evasions => !self.side_bitboards[us as usize],
+        };
+        let mut pseudo = MoveList::new();
+        self.gen_pseudo_legal(targets, &mut pseudo);
+
+        let legal = |mv: Move| {
+            let (from, to, code) = (mv.from(), mv.to(), mv.flag());
+            if from == king {
+                match code {
+                    // castling out of, through or into check is illegal
+                    FLAG_KING_CASTLE => {
+                        checkers.is_empty() && !attacked(from.offset(1)) && !attacked(to)
+                    }
+                    FLAG_QUEEN_CASTLE => {
+                        checkers.is_empty() && !attacked(from.offset(-1)) && !attacked(to)
+                    }
+                    _ => !attacked(to),
+                }
+            } else if code == FLAG_EP_CAPTURE {
+                // en passant removes two pieces from a rank at once, which the
+                // pin rays do not cover, so play it out
+                !self.apply(mv).is_square_attacked(king, them)
+            } else {
+                check_mask.contains(to)
+                    && (!pinned.contains(from) || pin_rays[from.index()].contains(to))
+            }
+        };
+        let wanted = |mv: Move| {
+            let quiet = !mv.is_capture() && mv.promotion().is_none();
+            match kind {
+                GenKind::All | GenKind::Evasions => true,
+                GenKind::Captures => !quiet,
+                GenKind::Quiets => quiet,
+                GenKind::QuietChecks => quiet && self.apply(mv).in_check(),
+            }
+        };
+        for mv in pseudo {
+            if wanted(mv) && legal(mv) {
+                list.push(mv);
             }
         }
-        moves
     }
 
-    fn gen_from_atk(&self, from: Square, atk: Bitboard) -> Vec<Move> {
-        bb_squares(atk & !self.side_bitboards[self.side as usize])
+    // perft counts the leaf nodes of the legal move tree to the given depth,
+    // counting the moves at the last ply instead of playing them out
+    // See: https://www.chessprogramming.org/Perft
+    pub fn perft(&self, depth: u8) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.generate_legal();
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        moves
             .iter()
-            .map(|&to| make_move(from, to, 0))
+            .map(|&mv| self.apply(mv).perft(depth - 1))
+            .sum()
+    }
+
+    // divide returns the perft count below each legal move, which narrows a
+    // wrong total down to the move generating it
+    pub fn divide(&self, depth: u8) -> Vec<(Move, u64)> {
+        self.generate_legal()
+            .into_iter()
+            .map(|mv| (mv, self.apply(mv).perft(depth.saturating_sub(1))))
             .collect()
     }
 
-    pub fn gen_king_moves(&self, from: Square, m: &masks::Lookup) -> Vec<Move> {
-        let mut ret = self.gen_from_atk(from, m.king[from as usize]);
+    fn gen_from_atk(&self, from: Square, atk: Bitboard, list: &mut MoveList) {
+        for to in atk & !self.side_bitboards[self.side as usize] {
+            if self.side_bitboards[self.side.other() as usize].contains(to) {
+                list.push(Move::new(from, to, FLAG_CAPTURE));
+            } else {
+                list.push(Move::new(from, to, FLAG_QUIET_MOVE));
+            }
+        }
+    }
+
+    pub fn gen_king_moves(&self, from: Square, targets: Bitboard, list: &mut MoveList) {
+        self.gen_from_atk(from, attacks::king_attacks(from) & targets, list);
+        // castling lands on an empty square
+        if (targets & !self.all_bitboard).is_empty() {
+            return;
+        }
         match self.side {
             enums::Colour::White => {
                 if self.castling & WKING_CASTLE_RIGHTS != 0
-                    && self.side_bitboards[self.side as usize] & 0x0000000000000060 == 0
+                    && (self.all_bitboard & Bitboard(0x0000000000000060)).is_empty()
                 {
-                    ret.push(make_move(
-                        enums::Square::E1 as Square,
-                        enums::Square::G1 as Square,
-                        FLAG_KING_CASTLE,
-                    ));
+                    list.push(Move::new(Square::E1, Square::G1, FLAG_KING_CASTLE));
                 }
                 if self.castling & WQUEEN_CASTLE_RIGHTS != 0
-                    && self.side_bitboards[self.side as usize] & 0x000000000000000e == 0
+                    && (self.all_bitboard & Bitboard(0x000000000000000e)).is_empty()
                 {
-                    ret.push(make_move(
-                        enums::Square::E1 as Square,
-                        enums::Square::C1 as Square,
-                        FLAG_QUEEN_CASTLE,
-                    ));
+                    list.push(Move::new(Square::E1, Square::C1, FLAG_QUEEN_CASTLE));
                 }
             }
             enums::Colour::Black => {
                 if self.castling & BKING_CASTLE_RIGHTS != 0
-                    && self.side_bitboards[self.side as usize] & 0x6000000000000000 == 0
+                    && (self.all_bitboard & Bitboard(0x6000000000000000)).is_empty()
                 {
-                    ret.push(make_move(
-                        enums::Square::E8 as Square,
-                        enums::Square::G8 as Square,
-                        FLAG_KING_CASTLE,
-                    ));
+                    list.push(Move::new(Square::E8, Square::G8, FLAG_KING_CASTLE));
                 }
                 if self.castling & BQUEEN_CASTLE_RIGHTS != 0
-                    && self.side_bitboards[self.side as usize] & 0x0e00000000000000 == 0
+                    && (self.all_bitboard & Bitboard(0x0e00000000000000)).is_empty()
                 {
-                    ret.push(make_move(
-                        enums::Square::E8 as Square,
-                        enums::Square::C8 as Square,
-                        FLAG_QUEEN_CASTLE,
-                    ));
+                    list.push(Move::new(Square::E8, Square::C8, FLAG_QUEEN_CASTLE));
                 }
             }
         }
-        ret
     }
 
-    pub fn gen_queen_moves(
-        &self,
-        from: Square,
-        m: &masks::Lookup,
-        t: &tables::Lookup,
-    ) -> Vec<Move> {
-        [
-            self.gen_rook_moves(from, m, t),
-            self.gen_bishop_moves(from, m, t),
-        ]
-        .concat()
+    pub fn gen_queen_moves(&self, from: Square, targets: Bitboard, list: &mut MoveList) {
+        let atk = attacks::queen_attacks(from, self.all_bitboard);
+        self.gen_from_atk(from, atk & targets, list)
+    }
+
+    pub fn gen_rook_moves(&self, from: Square, targets: Bitboard, list: &mut MoveList) {
+        let atk = attacks::rook_attacks(from, self.all_bitboard);
+        self.gen_from_atk(from, atk & targets, list)
     }
 
-    pub fn gen_rook_moves(&self, from: Square, m: &masks::Lookup, t: &tables::Lookup) -> Vec<Move> {
-        let hash = t.rmag[from as usize].transform(self.all_bitboard & m.rrel[from as usize]);
-        self.gen_from_atk(from, t.rmag_tbl[from as usize][hash as usize])
+    pub fn gen_bishop_moves(&self, from: Square, targets: Bitboard, list: &mut MoveList) {
+        let atk = attacks::bishop_attacks(from, self.all_bitboard);
+        self.gen_from_atk(from, atk & targets, list)
     }
 
-    pub fn gen_bishop_moves(
-        &self,
-        from: Square,
-        m: &masks::Lookup,
-        t: &tables::Lookup,
-    ) -> Vec<Move> {
-        let hash = t.bmag[from as usize].transform(self.all_bitboard & m.brel[from as usize]);
-        self.gen_from_atk(from, t.bmag_tbl[from as usize][hash as usize])
+    pub fn gen_knight_moves(&self, from: Square, targets: Bitboard, list: &mut MoveList) {
+        self.gen_from_atk(from, attacks::knight_attacks(from) & targets, list)
     }
 
-    pub fn gen_knight_moves(&self, from: Square, m: &masks::Lookup) -> Vec<Move> {
-        let mut ret: Vec<Move> = Vec::new();
-        for moves in self.gen_from_atk(from, m.knight[from as usize]) {
-            let locationfrom = move_get_from(moves);
-            let to = move_get_to(moves);
-            let code = move_get_code(moves);
-            if (self.side_bitboards[self.side as usize ^ 1] & (1 << to)) > 0 {
-                ret.push(make_move(locationfrom, to, FLAG_CAPTURE));
+    pub fn gen_pawn_moves(&self, from: Square, list: &mut MoveList) {
+        let rk = from.rank();
+        let (start_rank, last_rank) = match self.side {
+            enums::Colour::White => (1, 6),
+            enums::Colour::Black => (6, 1),
+        };
+
+        // moves onto the back rank become one move per promotion piece, with the
+        // flags for knight to queen being consecutive
+        let mut add = |to: Square, capture: bool| {
+            if rk == last_rank {
+                let first = if capture {
+                    FLAG_CAPTURE_PROMOTE_KNIGHT
+                } else {
+                    FLAG_PROMOTE_KNIGHT
+                };
+                for flag in first..first + 4 {
+                    list.push(Move::new(from, to, flag));
+                }
+            } else if capture {
+                list.push(Move::new(from, to, FLAG_CAPTURE));
             } else {
-                ret.push(make_move(locationfrom, to, FLAG_QUIET_MOVE));
+                list.push(Move::new(from, to, FLAG_QUIET_MOVE));
             }
-        }
+        };
 
-        ret
-    }
+        // move one square, this (reasonably) assumes the pawn can move forward
+        // if the square in front of it is unoccupied
+        let one = match self.side {
+            enums::Colour::White => from.offset(8),
+            enums::Colour::Black => from.offset(-8),
+        };
+        if !self.all_bitboard.contains(one) {
+            add(one, false);
+        }
 
-    pub fn gen_pawn_moves(&self, from: Square, m: &masks::Lookup) -> Vec<Move> {
-        let rk = from / 8;
-        let mut ret: Vec<Move> = Vec::new();
+        // do a capture
+        let targets = attacks::pawn_attacks(self.side, from);
+        for to in targets & self.side_bitboards[self.side.other() as usize] {
+            add(to, true);
+        }
+        if let Some(ep) = self.ep_target {
+            if targets.contains(ep) {
+                list.push(Move::new(from, ep, FLAG_EP_CAPTURE));
+            }
+        }
 
         // move forward two squares
-        // note 0x101 masks A1 and B1, we can shift this accordingly to describe the
+        // note 0x101 masks A1 and A2, we can shift this accordingly to describe the
         // two squares in front of the pawn
-        match self.side {
-            enums::Colour::White => {
-                if (rk == 1) && (self.all_bitboard & (0x101 << (from + 8)) == 0) {
-                    ret.push(make_move(from, from + 16, FLAG_DOUBLE_PAWN_PUSH));
-                }
-            }
-            enums::Colour::Black => {
-                if (rk == 6) && (self.all_bitboard & (0x101 << (from - 24)) == 0) {
-                    ret.push(make_move(from, from - 16, FLAG_DOUBLE_PAWN_PUSH));
-                }
+        if rk == start_rank {
+            let (lower, to) = match self.side {
+                enums::Colour::White => (from.offset(8), from.offset(16)),
+                enums::Colour::Black => (from.offset(-16), from.offset(-16)),
+            };
+            if (self.all_bitboard & (Bitboard(0x101) << lower.index() as u32)).is_empty() {
+                list.push(Move::new(from, to, FLAG_DOUBLE_PAWN_PUSH));
             }
         }
+    }
+}
 
-        // move one square, this (reasonably) assumes the pawn can move forward
-        // if the square in front of it is unoccupied
-        match self.side {
-            enums::Colour::White => {
-                if self.all_bitboard & (0x1 << (from + 8)) == 0 {
-                    ret.push(make_move(from, from + 8, FLAG_QUIET_MOVE));
-                }
-            }
-            enums::Colour::Black => {
-                if self.all_bitboard & (0x1 << (from - 8)) == 0 {
-                    ret.push(make_move(from, from - 8, FLAG_QUIET_MOVE));
-                }
+// between returns the squares strictly between a and b if they share a rank, file,
+// diagonal or anti-diagonal, and the empty set otherwise
+fn between(a: Square, b: Square) -> Bitboard {
+    let (a_bb, b_bb) = (a.bb(), b.bb());
+    if attacks::rook_attacks(a, Bitboard::EMPTY).contains(b) {
+        attacks::rook_attacks(a, b_bb) & attacks::rook_attacks(b, a_bb)
+    } else if attacks::bishop_attacks(a, Bitboard::EMPTY).contains(b) {
+        attacks::bishop_attacks(a, b_bb) & attacks::bishop_attacks(b, a_bb)
+    } else {
+        Bitboard::EMPTY
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::types::{
+        FLAG_CAPTURE_PROMOTE_BISHOP, FLAG_CAPTURE_PROMOTE_QUEEN, FLAG_CAPTURE_PROMOTE_ROOK,
+        FLAG_PROMOTE_BISHOP, FLAG_PROMOTE_QUEEN, FLAG_PROMOTE_ROOK,
+    };
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    pub(crate) const KIWIPETE: &str =
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    const POSITION_3: &str = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+    const POSITION_4: &str = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+    const POSITION_5: &str = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
+    const POSITION_6: &str =
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
+
+    // Reference counts from https://www.chessprogramming.org/Perft_Results
+    fn check_perft(fen: &str, expected: &[u64]) {
+        let pos = Position::new(fen);
+        for (depth, &nodes) in (1..).zip(expected) {
+            assert_eq!(pos.perft(depth), nodes, "{} at depth {}", fen, depth);
+        }
+    }
+
+    #[test]
+    fn perft_start() {
+        check_perft(START, &[20, 400, 8902, 197281]);
+    }
+
+    #[test]
+    fn perft_kiwipete() {
+        check_perft(KIWIPETE, &[48, 2039, 97862]);
+    }
+
+    #[test]
+    fn perft_position_3() {
+        check_perft(POSITION_3, &[14, 191, 2812, 43238, 674624]);
+    }
+
+    #[test]
+    fn perft_position_4() {
+        check_perft(POSITION_4, &[6, 264, 9467]);
+    }
+
+    #[test]
+    fn perft_position_5() {
+        check_perft(POSITION_5, &[44, 1486, 62379]);
+    }
+
+    #[test]
+    fn perft_position_6() {
+        check_perft(POSITION_6, &[46, 2079, 89890]);
+    }
+
+    fn has_move(fen: &str, from: &str, to: &str, code: u8) -> bool {
+        let sq = |s: &str| s.parse::<Square>().unwrap();
+        let mv = Move::new(sq(from), sq(to), code);
+        Position::new(fen).generate_legal().contains(&mv)
+    }
+
+    #[test]
+    fn flag_quiet_move() {
+        assert!(has_move(START, "g1", "f3", FLAG_QUIET_MOVE));
+        assert!(has_move(START, "e2", "e3", FLAG_QUIET_MOVE));
+        assert!(has_move(KIWIPETE, "e1", "d1", FLAG_QUIET_MOVE));
+    }
+
+    #[test]
+    fn flag_double_pawn_push() {
+        assert!(has_move(START, "e2", "e4", FLAG_DOUBLE_PAWN_PUSH));
+        assert!(has_move(POSITION_3, "g2", "g4", FLAG_DOUBLE_PAWN_PUSH));
+        assert!(!has_move(START, "e2", "e4", FLAG_QUIET_MOVE));
+    }
+
+    #[test]
+    fn flag_castles() {
+        assert!(has_move(KIWIPETE, "e1", "g1", FLAG_KING_CASTLE));
+        assert!(has_move(KIWIPETE, "e1", "c1", FLAG_QUEEN_CASTLE));
+        let black = "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1";
+        assert!(has_move(black, "e8", "g8", FLAG_KING_CASTLE));
+        assert!(has_move(black, "e8", "c8", FLAG_QUEEN_CASTLE));
+    }
+
+    #[test]
+    fn flag_capture_by_every_piece() {
+        assert!(has_move(
+            "4k3/8/8/8/8/2p5/8/1N2K3 w - - 0 1",
+            "b1",
+            "c3",
+            FLAG_CAPTURE
+        ));
+        assert!(has_move(
+            "4k3/8/8/8/8/5p2/8/3BK3 w - - 0 1",
+            "d1",
+            "f3",
+            FLAG_CAPTURE
+        ));
+        assert!(has_move(
+            "4k3/8/8/8/p7/8/8/R3K3 w - - 0 1",
+            "a1",
+            "a4",
+            FLAG_CAPTURE
+        ));
+        assert!(has_move(
+            "4k3/8/8/8/3p4/8/8/Q3K3 w - - 0 1",
+            "a1",
+            "d4",
+            FLAG_CAPTURE
+        ));
+        assert!(has_move(
+            "4k3/8/8/8/8/3p4/4P3/4K3 w - - 0 1",
+            "e2",
+            "d3",
+            FLAG_CAPTURE
+        ));
+        assert!(has_move(
+            "4k3/8/8/8/8/8/3p4/4K3 w - - 0 1",
+            "e1",
+            "d2",
+            FLAG_CAPTURE
+        ));
+    }
+
+    #[test]
+    fn flag_ep_capture() {
+        let fen = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1";
+        assert!(has_move(fen, "e5", "d6", FLAG_EP_CAPTURE));
+        assert!(!has_move(fen, "e5", "d6", FLAG_CAPTURE));
+    }
+
+    #[test]
+    fn flag_promotions() {
+        let fen = "1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+        for code in [
+            FLAG_PROMOTE_KNIGHT,
+            FLAG_PROMOTE_BISHOP,
+            FLAG_PROMOTE_ROOK,
+            FLAG_PROMOTE_QUEEN,
+        ] {
+            assert!(has_move(fen, "a7", "a8", code));
+        }
+        for code in [
+            FLAG_CAPTURE_PROMOTE_KNIGHT,
+            FLAG_CAPTURE_PROMOTE_BISHOP,
+            FLAG_CAPTURE_PROMOTE_ROOK,
+            FLAG_CAPTURE_PROMOTE_QUEEN,
+        ] {
+            assert!(has_move(fen, "a7", "b8", code));
+        }
+        assert!(!has_move(fen, "a7", "a8", FLAG_QUIET_MOVE));
+        assert!(!has_move(fen, "a7", "b8", FLAG_CAPTURE));
+    }
+
+    #[test]
+    fn parses_uci_moves() {
+        let kiwipete = Position::new(KIWIPETE);
+        for (text, flag) in [
+            ("e2a6", FLAG_CAPTURE),
+            ("a2a4", FLAG_DOUBLE_PAWN_PUSH),
+            ("e1g1", FLAG_KING_CASTLE),
+            ("e1c1", FLAG_QUEEN_CASTLE),
+        ] {
+            assert_eq!(kiwipete.parse_uci_move(text).map(|mv| mv.flag()), Ok(flag));
+        }
+        for mv in kiwipete.generate_legal() {
+            assert_eq!(kiwipete.parse_uci_move(&utils::move_string(mv)), Ok(mv));
+        }
+
+        let pos = Position::new("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2");
+        assert_eq!(
+            pos.parse_uci_move("e5d6").map(|mv| mv.flag()),
+            Ok(FLAG_EP_CAPTURE)
+        );
+        let pos = Position::new("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(
+            pos.parse_uci_move("a7b8r").map(|mv| mv.flag()),
+            Ok(FLAG_CAPTURE_PROMOTE_ROOK)
+        );
+        assert_eq!(pos.parse_uci_move("a7a8"), Err(UciMoveError::Illegal));
+        assert_eq!(pos.parse_uci_move("a7a8k"), Err(UciMoveError::Syntax));
+        assert_eq!(pos.parse_uci_move("e1e3"), Err(UciMoveError::Illegal));
+        assert_eq!(pos.parse_uci_move("e1e2q"), Err(UciMoveError::Illegal));
+        for text in ["", "e1", "e1e", "e1e2qq", "0000", "e1-e2", "é1e2"] {
+            assert_eq!(
+                pos.parse_uci_move(text),
+                Err(UciMoveError::Syntax),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn capture_flag_matches_occupancy() {
+        for fen in [
+            START, KIWIPETE, POSITION_3, POSITION_4, POSITION_5, POSITION_6,
+        ] {
+            let pos = Position::new(fen);
+            for mv in pos.generate_legal() {
+                let occupied = pos.piece_at(mv.to()).is_some();
+                assert_eq!(occupied, mv.is_capture() && mv.flag() != FLAG_EP_CAPTURE);
             }
         }
+    }
 
-        // do a capture
-        let targets = bb_squares(m.pcapture[self.side as usize][from as usize]);
-        for to in targets {
-            if to == self.ep_target {
-                ret.push(make_move(from, to, FLAG_EP_CAPTURE));
-            } else if (1 << to) & self.side_bitboards[self.side as usize ^ 1] != 0 {
-                ret.push(make_move(from, to, FLAG_CAPTURE));
-            }
-        }
-
-        // potentially, moves one step forward are promotions. go through the list of
-        // all moves generated and turn each move into 4 moves, corresponding to 4
-        // different promotions.
-        let promotions = [
-            make_move(0, 0, FLAG_PROMOTE_KNIGHT),
-            make_move(0, 0, FLAG_PROMOTE_BISHOP),
-            make_move(0, 0, FLAG_PROMOTE_ROOK),
-            make_move(0, 0, FLAG_PROMOTE_QUEEN),
+    #[test]
+    fn fen_round_trip() {
+        for fen in [
+            START,
+            KIWIPETE,
+            POSITION_3,
+            POSITION_4,
+            POSITION_5,
+            POSITION_6,
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "8/8/4k3/8/8/4K3/8/8 b - - 37 92",
+        ] {
+            assert_eq!(Position::new(fen).to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn fen_counters_default_when_missing() {
+        let pos = Position::new("8/8/4k3/8/8/4K3/8/8 w - -");
+        assert_eq!(pos.to_fen(), "8/8/4k3/8/8/4K3/8/8 w - - 0 1");
+    }
+
+    #[test]
+    fn en_passant_key_needs_a_capturer() {
+        let key = |fen: &str| Position::new(fen).key();
+
+        // the target is kept as given, but without a capturer not hashed
+        let fen = "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2";
+        assert_eq!(Position::new(fen).to_fen(), fen);
+        assert_eq!(
+            key(fen),
+            key("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2")
+        );
+
+        let e4 = Move::new(Square::E2, Square::E4, FLAG_DOUBLE_PAWN_PUSH);
+        let pos = Position::new(START).apply(e4);
+        assert_eq!(
+            pos.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+        assert_eq!(
+            pos.key(),
+            key("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1")
+        );
+
+        // with a pawn able to take, the target counts
+        let d5 = Move::new(Square::D7, Square::D5, FLAG_DOUBLE_PAWN_PUSH);
+        let pos = Position::new("4k3/3p4/8/4P3/8/8/8/4K3 b - - 0 1").apply(d5);
+        assert_eq!(pos.to_fen(), "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2");
+        assert_eq!(pos.key(), key("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2"));
+        assert_ne!(pos.key(), key("4k3/8/8/3pP3/8/8/8/4K3 w - - 0 2"));
+    }
+
+    #[test]
+    fn fen_errors() {
+        let cases = [
+            (
+                "",
+                FenError::Missing {
+                    field: FenField::Placement,
+                },
+            ),
+            (
+                "8/8/8/8/8/8/8/8",
+                FenError::Missing {
+                    field: FenField::Side,
+                },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K2 w - - 0 1",
+                FenError::Invalid {
+                    field: FenField::Placement,
+                    offset: 19,
+                },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3R w - - 0 1",
+                FenError::Invalid {
+                    field: FenField::Placement,
+                    offset: 19,
+                },
+            ),
+            (
+                "4k3/8/8/9/8/8/8/4K3 w - - 0 1",
+                FenError::Invalid {
+                    field: FenField::Placement,
+                    offset: 8,
+                },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/8/4K3 w - - 0 1",
+                FenError::Invalid {
+                    field: FenField::Placement,
+                    offset: 18,
+                },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 x - - 0 1",
+                FenError::Invalid {
+                    field: FenField::Side,
+                    offset: 20,
+                },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w KX - 0 1",
+                FenError::Invalid {
+                    field: FenField::Castling,
+                    offset: 23,
+                },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - z9 0 1",
+                FenError::Invalid {
+                    field: FenField::EnPassant,
+                    offset: 24,
+                },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - x 1",
+                FenError::Invalid {
+                    field: FenField::Halfmove,
+                    offset: 26,
+                },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - 0 1 1",
+                FenError::Trailing { offset: 30 },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4KK2 w - - 0 1",
+                FenError::KingCount {
+                    colour: enums::Colour::White,
+                    kings: 2,
+                },
+            ),
+            (
+                "8/8/8/8/8/8/8/4K3 w - - 0 1",
+                FenError::KingCount {
+                    colour: enums::Colour::Black,
+                    kings: 0,
+                },
+            ),
+            (
+                "P3k3/8/8/8/8/8/8/4K3 w - - 0 1",
+                FenError::PawnOnBackRank { square: Square::A8 },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w K - 0 1",
+                FenError::CastlingWithoutPieces { right: 'K' },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - e6 0 1",
+                FenError::ImpossibleEnPassant { square: Square::E6 },
+            ),
+            (
+                "4k3/8/8/4p3/8/8/8/4K3 w - e3 0 1",
+                FenError::ImpossibleEnPassant { square: Square::E3 },
+            ),
+            ("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1", FenError::OpponentInCheck),
         ];
-        match self.side {
-            enums::Colour::White => {
-                if rk == 6 {
-                    ret = ret
-                        .iter()
-                        .flat_map(|mv| promotions.iter().map(|p| mv | p).collect::<Vec<_>>())
-                        .collect()
-                }
-            }
-            enums::Colour::Black => {
-                if rk == 1 {
-                    ret = ret
-                        .iter()
-                        .flat_map(|mv| promotions.iter().map(|p| mv | p).collect::<Vec<_>>())
-                        .collect()
-                }
+        for (fen, err) in cases {
+            assert_eq!(Position::from_fen(fen).err(), Some(err), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn apply_updates_counters() {
+        let pos = Position::new(START);
+        let e4 = Move::new(Square::E2, Square::E4, FLAG_DOUBLE_PAWN_PUSH);
+        let nf6 = Move::new(Square::G8, Square::F6, FLAG_QUIET_MOVE);
+        let nf3 = Move::new(Square::G1, Square::F3, FLAG_QUIET_MOVE);
+        let pos = pos.apply(e4).apply(nf6).apply(nf3);
+        assert_eq!(
+            pos.to_fen(),
+            "rnbqkb1r/pppppppp/5n2/8/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 2 2"
+        );
+
+        let kd7 = Move::new(Square::E8, Square::D7, FLAG_QUIET_MOVE);
+        let pos = Position::new("4k3/8/8/8/8/8/8/4K3 b - - 65535 65535").apply(kd7);
+        assert_eq!(pos.to_fen(), "8/3k4/8/8/8/8/8/4K3 w - - 65535 65535");
+    }
+
+    // walks the move tree checking the incremental key against a full recompute
+    fn check_keys(pos: &Position, depth: u8) {
+        assert_eq!(pos.key(), pos.hash(), "{}", pos.to_fen());
+        if depth > 0 {
+            for mv in pos.generate_legal() {
+                check_keys(&pos.apply(mv), depth - 1);
             }
         }
+    }
 
-        ret
+    #[test]
+    fn incremental_key_matches_hash() {
+        for fen in [KIWIPETE, POSITION_3, POSITION_4, POSITION_5] {
+            check_keys(&Position::new(fen), 3);
+        }
+    }
+
+    #[test]
+    fn key_identifies_positions() {
+        let sq = |s: &str| s.parse::<Square>().unwrap();
+        let quiet = |from, to| Move::new(sq(from), sq(to), FLAG_QUIET_MOVE);
+
+        // the same position reached by different move orders
+        let start = Position::new(START);
+        let a = start
+            .apply(quiet("g1", "f3"))
+            .apply(quiet("g8", "f6"))
+            .apply(quiet("b1", "c3"));
+        let b = start
+            .apply(quiet("b1", "c3"))
+            .apply(quiet("g8", "f6"))
+            .apply(quiet("g1", "f3"));
+        assert_eq!(a.key(), b.key());
+        assert_ne!(a.key(), start.key());
+
+        // side to move, castling rights and en passant file all count
+        let keys = [
+            "4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1",
+            "4k3/8/8/8/8/8/8/R3K2R b KQ - 0 1",
+            "4k3/8/8/8/8/8/8/R3K2R w K - 0 1",
+            "4k3/8/8/8/8/8/8/R3K2R w - - 0 1",
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1",
+            "4k3/8/8/3pP3/8/8/8/4K3 w - - 0 1",
+        ]
+        .map(|fen| Position::new(fen).key());
+        for (i, a) in keys.iter().enumerate() {
+            assert!(keys[i + 1..].iter().all(|b| a != b));
+        }
     }
-}
 
-pub fn bb_squares(bb: Bitboard) -> Vec<Square> {
-    let mut bb = bb as i64;
-    let mut set: Vec<Square> = Vec::new();
-    while bb != 0 {
-        let lsb = (bb & 0i64.wrapping_sub(bb)) as Bitboard;
-        set.push(lsb.trailing_zeros() as Square);
-        bb &= bb.wrapping_sub(1);
+    #[test]
+    fn divide_sums_to_perft() {
+        let pos = Position::new(KIWIPETE);
+        let divided = pos.divide(2);
+        assert_eq!(divided.len(), 48);
+        assert_eq!(divided.iter().map(|&(_, n)| n).sum::<u64>(), 2039);
+    }
+
+    #[test]
+    fn attack_queries() {
+        let sq = |s: &str| s.parse::<Square>().unwrap();
+        let bb = |squares: &[&str]| squares.iter().map(|&s| sq(s)).collect::<Bitboard>();
+
+        // white is checked by the rook on h1, and the bishop pins the knight
+        let pos = Position::new("4k3/8/4n3/8/1b6/8/3NR3/4K2r w - - 0 1");
+        assert!(pos.in_check());
+        assert_eq!(pos.checkers(), bb(&["h1"]));
+        assert_eq!(pos.pinned(enums::Colour::White), bb(&["d2"]));
+        assert_eq!(pos.pinned(enums::Colour::Black), bb(&["e6"]));
+
+        assert!(pos.is_square_attacked(sq("f1"), enums::Colour::Black));
+        assert!(pos.is_square_attacked(sq("c3"), enums::Colour::Black));
+        assert!(pos.is_square_attacked(sq("e6"), enums::Colour::White));
+        assert!(!pos.is_square_attacked(sq("e7"), enums::Colour::White));
+
+        assert_eq!(pos.attackers_to(sq("c3"), pos.all_bitboard), bb(&["b4"]));
+        assert_eq!(
+            pos.attackers_to(sq("e1"), pos.all_bitboard ^ bb(&["d2"])),
+            bb(&["b4", "e2", "h1"])
+        );
+
+        let pos = Position::new(START);
+        assert!(!pos.in_check());
+        assert_eq!(pos.checkers(), Bitboard::EMPTY);
+        assert_eq!(pos.pinned(enums::Colour::White), Bitboard::EMPTY);
     }
-    set
-}
 
-pub fn make_bb(set: Vec<u8>) -> Bitboard {
-    set.iter().fold(0, |acc, bb| acc ^ (1u64 << bb))
+    // perft_staged counts like perft, but from the captures and the quiets
+    fn perft_staged(pos: &Position, depth: u8) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let mut list = MoveList::new();
+        pos.generate(GenKind::Captures, &mut list);
+        pos.generate(GenKind::Quiets, &mut list);
+        list.into_iter()
+            .map(|mv| perft_staged(&pos.apply(mv), depth - 1))
+            .sum()
+    }
+
+    // check_kinds checks the move kinds against generate_legal throughout the
+    // tree below pos
+    fn check_kinds(pos: &Position, depth: u8) {
+        let legal = pos.generate_legal();
+        let kind = |kind| {
+            let mut list = MoveList::new();
+            pos.generate(kind, &mut list);
+            list
+        };
+        let (captures, quiets) = (kind(GenKind::Captures), kind(GenKind::Quiets));
+        assert_eq!(captures.len() + quiets.len(), legal.len());
+        assert!(captures
+            .iter()
+            .chain(quiets.iter())
+            .all(|mv| legal.contains(mv)));
+
+        let evasions = kind(GenKind::Evasions);
+        if pos.in_check() {
+            assert_eq!(&evasions[..], &legal[..]);
+        } else {
+            assert!(evasions.is_empty());
+        }
+
+        let checks: Vec<Move> = quiets
+            .iter()
+            .copied()
+            .filter(|&mv| pos.apply(mv).in_check())
+            .collect();
+        assert_eq!(&kind(GenKind::QuietChecks)[..], &checks[..]);
+
+        if depth > 1 {
+            for mv in legal {
+                check_kinds(&pos.apply(mv), depth - 1);
+            }
+        }
+    }
+
+    #[test]
+    fn staged_generation_matches_legal() {
+        for fen in [START, KIWIPETE, POSITION_3, POSITION_4, POSITION_5] {
+            check_kinds(&Position::new(fen), 2);
+        }
+        assert_eq!(perft_staged(&Position::new(KIWIPETE), 3), 97862);
+        assert_eq!(perft_staged(&Position::new(POSITION_4), 3), 9467);
+        assert_eq!(perft_staged(&Position::new(POSITION_3), 4), 43238);
+    }
+
+    #[test]
+    fn staged_generation_kinds() {
+        let count = |fen: &str, kind| {
+            let mut list = MoveList::new();
+            Position::new(fen).generate(kind, &mut list);
+            list.len()
+        };
+        // captures include quiet promotions, quiets include castling
+        assert_eq!(
+            count("4k3/1P6/8/8/8/8/8/R3K3 w Q - 0 1", GenKind::Captures),
+            4
+        );
+        assert_eq!(
+            count("4k3/1P6/8/8/8/8/8/R3K3 w Q - 0 1", GenKind::Quiets),
+            5 + 10 + 1
+        );
+        // Ra8, Rd1 and castling, which brings the rook to d1, give check
+        assert_eq!(
+            count("3k4/8/8/8/8/8/8/R3K3 w Q - 0 1", GenKind::QuietChecks),
+            3
+        );
+    }
 }
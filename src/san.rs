@@ -0,0 +1,304 @@
+use crate::positions::Position;
+use crate::types::{Move, Square, FLAG_KING_CASTLE, FLAG_QUEEN_CASTLE};
+use crate::{enums, utils};
+
+// Standard Algebraic Notation, as used by PGN and people
+// See: https://www.chessprogramming.org/Algebraic_Chess_Notation#SAN
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SanError {
+    // The text does not read as a move
+    Syntax,
+    // No legal move fits the text
+    Illegal,
+    // More than one legal move fits the text, e.g. Nd7 with knights on b8 and f6
+    Ambiguous,
+}
+
+impl std::fmt::Display for SanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SanError::Syntax => write!(f, "not a move"),
+            SanError::Illegal => write!(f, "no legal move matches"),
+            SanError::Ambiguous => write!(f, "more than one legal move matches"),
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+// format writes mv, a legal move in pos, in SAN: the piece, as much of the from
+// square as tells it apart from other pieces of its kind reaching the same
+// square, the capture, the destination, the promotion and the check or mate
+pub fn format(pos: &Position, mv: Move) -> String {
+    let mut ret = match mv.flag() {
+        FLAG_KING_CASTLE => String::from("O-O"),
+        FLAG_QUEEN_CASTLE => String::from("O-O-O"),
+        _ => {
+            let (from, to) = (mv.from(), mv.to());
+            let (_, piece) = pos
+                .piece_at(from)
+                .expect("no piece on the from square of the move");
+            let mut ret = String::new();
+            if piece == enums::Piece::Pawn {
+                // pawns are only named when capturing, by their file
+                if mv.is_capture() {
+                    ret.push(file_char(from));
+                }
+            } else {
+                ret.push(utils::colour_piece_ascii(enums::Colour::White, piece));
+                ret.push_str(&disambiguation(pos, mv, piece));
+            }
+            if mv.is_capture() {
+                ret.push('x');
+            }
+            ret.push_str(&to.to_string());
+            if let Some(promotion) = mv.promotion() {
+                ret.push('=');
+                ret.push(utils::colour_piece_ascii(enums::Colour::White, promotion));
+            }
+            ret
+        }
+    };
+
+    let next = pos.apply(mv);
+    if next.in_check() {
+        ret.push(if next.generate_legal().is_empty() {
+            '#'
+        } else {
+            '+'
+        });
+    }
+    ret
+}
+
+// disambiguation returns the file of the from square if that is enough to tell
+// mv from the other moves of the same kind of piece to the same square, else
+// the rank if that is, else the whole square
+fn disambiguation(pos: &Position, mv: Move, piece: enums::Piece) -> String {
+    let from = mv.from();
+    let rivals: Vec<Square> = pos
+        .generate_legal()
+        .into_iter()
+        .filter(|other| other.to() == mv.to() && other.from() != from)
+        .filter(|other| pos.piece_at(other.from()).map(|(_, p)| p) == Some(piece))
+        .map(|other| other.from())
+        .collect();
+    if rivals.is_empty() {
+        String::new()
+    } else if rivals.iter().all(|sq| sq.file() != from.file()) {
+        file_char(from).to_string()
+    } else if rivals.iter().all(|sq| sq.rank() != from.rank()) {
+        rank_char(from).to_string()
+    } else {
+        from.to_string()
+    }
+}
+
+fn file_char(sq: Square) -> char {
+    (b'a' + sq.file()) as char
+}
+
+fn rank_char(sq: Square) -> char {
+    (b'1' + sq.rank()) as char
+}
+
+// parse finds the legal move of pos that text describes. It takes SAN as well
+// as the usual liberties of hand-written moves: castling with zeros, missing or
+// extra capture signs, long algebraic such as Ng1-f3 or e2e4, promotions
+// without the = sign or piece, which then mean a queen, and trailing check
+// signs, annotations and e.p.
+pub fn parse(pos: &Position, text: &str) -> Result<Move, SanError> {
+    let text = text.trim();
+    let text = text.strip_suffix("e.p.").unwrap_or(text).trim_end();
+    let text = text.trim_end_matches(['+', '#', '!', '?']);
+    let legal = pos.generate_legal();
+
+    let castle = match text {
+        "O-O" | "0-0" | "o-o" => Some(FLAG_KING_CASTLE),
+        "O-O-O" | "0-0-0" | "o-o-o" => Some(FLAG_QUEEN_CASTLE),
+        _ => None,
+    };
+    if let Some(flag) = castle {
+        return legal
+            .into_iter()
+            .find(|mv| mv.flag() == flag)
+            .ok_or(SanError::Illegal);
+    }
+
+    let mut chars: Vec<char> = text
+        .chars()
+        .filter(|c| !matches!(c, 'x' | ':' | '-' | '=' | '(' | ')'))
+        .collect();
+
+    // a leading piece letter, where a lowercase b is the b-file
+    let piece = match chars.first() {
+        Some(&c) if c != 'b' => match utils::ascii_colour_piece(c) {
+            Some((_, piece)) => {
+                chars.remove(0);
+                Some(piece)
+            }
+            None => None,
+        },
+        _ => None,
+    };
+
+    // a trailing promotion piece
+    let promotion = match chars.last().and_then(|&c| utils::ascii_colour_piece(c)) {
+        Some((_, piece)) => {
+            chars.pop();
+            Some(piece)
+        }
+        None => None,
+    };
+
+    // the destination, after up to a file and a rank of the from square
+    if chars.len() < 2 || chars.len() > 4 {
+        return Err(SanError::Syntax);
+    }
+    let (hints, dest) = chars.split_at(chars.len() - 2);
+    let to: Square = dest
+        .iter()
+        .collect::<String>()
+        .parse()
+        .map_err(|_| SanError::Syntax)?;
+    let (mut file, mut rank) = (None, None);
+    for &c in hints {
+        match c {
+            'a'..='h' if file.is_none() => file = Some(c as u8 - b'a'),
+            '1'..='8' if rank.is_none() => rank = Some(c as u8 - b'1'),
+            _ => return Err(SanError::Syntax),
+        }
+    }
+
+    // without a piece letter, a pawn is meant unless the whole from square is
+    // given as in e2e4
+    let piece = match (piece, file, rank) {
+        (Some(piece), _, _) => Some(piece),
+        (None, Some(_), Some(_)) => None,
+        (None, _, _) => Some(enums::Piece::Pawn),
+    };
+
+    let mut matches = legal.into_iter().filter(|mv| {
+        mv.to() == to
+            && file.is_none_or(|f| mv.from().file() == f)
+            && rank.is_none_or(|r| mv.from().rank() == r)
+            && piece.is_none_or(|p| pos.piece_at(mv.from()).map(|(_, q)| q) == Some(p))
+            && match (promotion, mv.promotion()) {
+                (Some(wanted), Some(p)) => wanted == p,
+                (None, Some(p)) => p == enums::Piece::Queen,
+                (None, None) => true,
+                (Some(_), None) => false,
+            }
+    });
+    match (matches.next(), matches.next()) {
+        (Some(mv), None) => Ok(mv),
+        (None, _) => Err(SanError::Illegal),
+        (Some(_), Some(_)) => Err(SanError::Ambiguous),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::positions::tests::KIWIPETE;
+    use crate::uci;
+
+    // san formats the move written as from-to in long algebraic
+    fn san(fen: &str, uci: &str) -> String {
+        let pos = Position::new(fen);
+        let mv = pos.parse_uci_move(uci).unwrap();
+        format(&pos, mv)
+    }
+
+    #[test]
+    fn formats_moves() {
+        assert_eq!(san(uci::STARTPOS, "g1f3"), "Nf3");
+        assert_eq!(san(uci::STARTPOS, "e2e4"), "e4");
+        let knights = "rnbqkb1r/ppp1pppp/5n2/3p4/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1";
+        assert_eq!(san(knights, "b8d7"), "Nbd7");
+        assert_eq!(san(knights, "f6d7"), "Nfd7");
+        let rooks = "4k3/8/8/R7/8/8/8/R3K3 w - - 0 1";
+        assert_eq!(san(rooks, "a1a3"), "R1a3");
+        assert_eq!(san(rooks, "a5a3"), "R5a3");
+        let queens = "2k5/8/8/8/4Q2Q/8/8/K6Q w - - 0 1";
+        assert_eq!(san(queens, "h4e1"), "Qh4e1");
+        assert_eq!(san(queens, "e4e1"), "Qee1");
+        assert_eq!(san(queens, "h1e1"), "Q1e1");
+        assert_eq!(san(KIWIPETE, "e1c1"), "O-O-O");
+        assert_eq!(san(KIWIPETE, "e1g1"), "O-O");
+        assert_eq!(san(KIWIPETE, "d5e6"), "dxe6");
+        assert_eq!(san("k7/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7e8q"), "e8=Q+");
+        let promotions = "1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(san(promotions, "a7b8n"), "axb8=N");
+        assert_eq!(san(promotions, "a7a8q"), "a8=Q");
+        let scholar = "r1bqkbnr/pppp1ppp/2n5/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4";
+        assert_eq!(san(scholar, "h5f7"), "Qxf7#");
+        assert_eq!(san("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), "exd6");
+    }
+
+    #[test]
+    fn parses_formatted_moves() {
+        for fen in [
+            uci::STARTPOS,
+            KIWIPETE,
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "2k5/8/8/8/4Q2Q/8/8/K6Q w - - 0 1",
+        ] {
+            let pos = Position::new(fen);
+            for mv in pos.generate_legal() {
+                assert_eq!(parse(&pos, &format(&pos, mv)), Ok(mv), "{}", fen);
+            }
+        }
+    }
+
+    #[test]
+    fn parses_lenient_input() {
+        let parsed =
+            |fen: &str, text: &str| parse(&Position::new(fen), text).map(utils::move_string);
+        for text in [
+            "Nf3", "Ng1-f3", "g1f3", "Ngf3", "N1f3", "Nf3!?", " Nf3+ ", "nf3",
+        ] {
+            assert_eq!(
+                parsed(uci::STARTPOS, text),
+                Ok(String::from("g1f3")),
+                "{}",
+                text
+            );
+        }
+        assert_eq!(parsed(uci::STARTPOS, "e2-e4"), Ok(String::from("e2e4")));
+        assert_eq!(parsed(uci::STARTPOS, "Pe4"), Ok(String::from("e2e4")));
+        assert_eq!(parsed(KIWIPETE, "0-0"), Ok(String::from("e1g1")));
+        assert_eq!(parsed(KIWIPETE, "O-O-O"), Ok(String::from("e1c1")));
+        assert_eq!(parsed(KIWIPETE, "e1g1"), Ok(String::from("e1g1")));
+        assert_eq!(parsed(KIWIPETE, "Bxa6"), Ok(String::from("e2a6")));
+        assert_eq!(parsed(KIWIPETE, "de6"), Ok(String::from("d5e6")));
+        let ep = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1";
+        assert_eq!(parsed(ep, "exd6 e.p."), Ok(String::from("e5d6")));
+        let promotions = "1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(parsed(promotions, "a8"), Ok(String::from("a7a8q")));
+        assert_eq!(parsed(promotions, "a8N"), Ok(String::from("a7a8n")));
+        assert_eq!(parsed(promotions, "axb8=R"), Ok(String::from("a7b8r")));
+        assert_eq!(parsed(promotions, "a7b8b"), Ok(String::from("a7b8b")));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let knights = "rnbqkb1r/ppp1pppp/5n2/3p4/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1";
+        let error = |fen: &str, text: &str| parse(&Position::new(fen), text).err();
+        assert_eq!(error(knights, "Nd7"), Some(SanError::Ambiguous));
+        assert_eq!(error(uci::STARTPOS, "Nd2"), Some(SanError::Illegal));
+        assert_eq!(error(uci::STARTPOS, "e5"), Some(SanError::Illegal));
+        assert_eq!(error(uci::STARTPOS, "O-O"), Some(SanError::Illegal));
+        assert_eq!(error(uci::STARTPOS, "Nf3=Q"), Some(SanError::Illegal));
+        for text in ["", "hello", "Nz9", "e", "Ra1b2c3"] {
+            assert_eq!(
+                error(uci::STARTPOS, text),
+                Some(SanError::Syntax),
+                "{}",
+                text
+            );
+        }
+    }
+}
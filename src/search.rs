@@ -0,0 +1,293 @@
+use crate::eval;
+use crate::game::{self, Game};
+use crate::positions::Position;
+use crate::types::Move;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+// Scores are in centipawns from the side to move's point of view. Mate scores
+// count down from MATE by the number of plies to the mate, so shorter mates
+// score higher.
+pub const MATE: i32 = 30000;
+pub const INFINITY: i32 = 32000;
+const MAX_PLY: u8 = 64;
+
+// is_mate tells whether score is a mate score rather than an evaluation
+pub fn is_mate(score: i32) -> bool {
+    score.abs() >= MATE - MAX_PLY as i32
+}
+
+// mate_in gives the moves to the mate of a mate score, negative when the side
+// to move is getting mated
+pub fn mate_in(score: i32) -> Option<i32> {
+    if !is_mate(score) {
+        return None;
+    }
+    let moves = (MATE - score.abs() + 1) / 2;
+    Some(if score > 0 { moves } else { -moves })
+}
+
+// score_string formats a score as UCI does, cp <centipawns> or mate <moves>
+pub fn score_string(score: i32) -> String {
+    match mate_in(score) {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", score),
+    }
+}
+
+#[derive(Default)]
+pub struct Limits {
+    pub depth: Option<u8>,
+    pub nodes: Option<u64>,
+    pub time: Option<Duration>,
+}
+
+// Outcome of the deepest completed iteration
+#[derive(Clone, Default)]
+pub struct Report {
+    pub best: Option<Move>,
+    pub score: i32,
+    pub pv: Vec<Move>,
+    pub depth: u8,
+    pub nodes: u64,
+    pub elapsed: Duration,
+}
+
+struct Searcher<'a> {
+    limits: &'a Limits,
+    stop: &'a AtomicBool,
+    start: Instant,
+    nodes: u64,
+    aborted: bool,
+
+    // principal variation of the previous iteration, searched first
+    prev_pv: Vec<Move>,
+
+    // keys of the game so far and then of the line being searched, ending with
+    // the current position
+    keys: Vec<u64>,
+}
+
+// search runs iterative deepening negamax from the current position of game
+// until a limit is hit or stop is set, calling report after each completed
+// iteration. The first iteration always completes so there is a move to play.
+// See: https://www.chessprogramming.org/Iterative_Deepening
+pub fn search(
+    game: &Game,
+    limits: &Limits,
+    stop: &AtomicBool,
+    mut report: impl FnMut(&Report),
+) -> Report {
+    let mut s = Searcher {
+        limits,
+        stop,
+        start: Instant::now(),
+        nodes: 0,
+        aborted: false,
+        prev_pv: Vec::new(),
+        keys: game.keys(),
+    };
+    let pos = game.position();
+    let mut ret = Report::default();
+
+    let max_depth = limits.depth.unwrap_or(MAX_PLY).clamp(1, MAX_PLY);
+    for depth in 1..=max_depth {
+        let mut pv = Vec::new();
+        let score = s.negamax(pos, depth, 0, -INFINITY, INFINITY, &mut pv);
+        if s.aborted {
+            break;
+        }
+
+        s.prev_pv = pv.clone();
+        ret = Report {
+            best: pv.first().copied(),
+            score,
+            pv,
+            depth,
+            nodes: s.nodes,
+            elapsed: s.start.elapsed(),
+        };
+        report(&ret);
+
+        // no point in searching deeper once there are no moves or a mate is found
+        if ret.best.is_none() || is_mate(score) {
+            break;
+        }
+    }
+    ret
+}
+
+impl<'a> Searcher<'a> {
+    fn out_of_budget(&mut self) -> bool {
+        if self.aborted {
+            return true;
+        }
+        // the clock is only read every so often
+        self.aborted = self.stop.load(Ordering::Relaxed)
+            || self.limits.nodes.is_some_and(|n| self.nodes >= n)
+            || (self.nodes.is_multiple_of(1024)
+                && self.limits.time.is_some_and(|d| self.start.elapsed() >= d));
+        self.aborted
+    }
+
+    // negamax returns the score of pos searched to depth within the alpha-beta
+    // window, filling pv with the best line found
+    // See: https://www.chessprogramming.org/Alpha-Beta
+    fn negamax(
+        &mut self,
+        pos: &Position,
+        depth: u8,
+        ply: u8,
+        mut alpha: i32,
+        beta: i32,
+        pv: &mut Vec<Move>,
+    ) -> i32 {
+        pv.clear();
+        // the first iteration runs to completion whatever the limits
+        if !self.prev_pv.is_empty() && self.out_of_budget() {
+            return 0;
+        }
+        self.nodes += 1;
+
+        // a position seen before on the way here or in the game is scored as a
+        // draw at once, since the side able to avoid repeating did not
+        if ply > 0 && game::repetitions(&self.keys, pos.halfmove()) >= 2 {
+            return 0;
+        }
+
+        let mut moves = pos.generate_legal();
+        if moves.is_empty() {
+            return if pos.in_check() {
+                -MATE + ply as i32
+            } else {
+                0
+            };
+        }
+        // checked after mate, which wins even on the hundredth ply
+        if ply > 0 && (pos.halfmove() >= 100 || pos.has_insufficient_material()) {
+            return 0;
+        }
+        if depth == 0 || ply >= MAX_PLY {
+            return eval::evaluate(pos);
+        }
+
+        // previous principal variation first, then captures
+        let pv_move = self.prev_pv.get(ply as usize).copied();
+        moves.sort_by_key(|&mv| (Some(mv) != pv_move, !mv.is_capture()));
+
+        let mut child_pv = Vec::new();
+        for mv in moves {
+            let child = pos.apply(mv);
+            self.keys.push(child.key());
+            let score = -self.negamax(&child, depth - 1, ply + 1, -beta, -alpha, &mut child_pv);
+            self.keys.pop();
+            if self.aborted {
+                return 0;
+            }
+            if score > alpha {
+                alpha = score;
+                pv.clear();
+                pv.push(mv);
+                pv.extend_from_slice(&child_pv);
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+        alpha
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils;
+
+    fn run(fen: &str, depth: u8) -> Report {
+        let limits = Limits {
+            depth: Some(depth),
+            ..Default::default()
+        };
+        search(
+            &Game::new(Position::new(fen)),
+            &limits,
+            &AtomicBool::new(false),
+            |_| {},
+        )
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        let report = run("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 3);
+        assert_eq!(utils::move_string(report.best.unwrap()), "a1a8");
+        assert_eq!(report.score, MATE - 1);
+    }
+
+    #[test]
+    fn finds_mate_in_two() {
+        let report = run("7k/8/5K2/8/8/8/8/R7 w - - 0 1", 4);
+        assert_eq!(report.score, MATE - 3);
+        assert_eq!(report.pv.len(), 3);
+    }
+
+    #[test]
+    fn formats_scores() {
+        assert_eq!(score_string(35), "cp 35");
+        assert_eq!(score_string(MATE - 1), "mate 1");
+        assert_eq!(score_string(MATE - 3), "mate 2");
+        assert_eq!(score_string(-MATE + 2), "mate -1");
+        assert_eq!(mate_in(MATE - 4), Some(2));
+        assert_eq!(mate_in(-35), None);
+    }
+
+    #[test]
+    fn scores_mated_and_stalemated() {
+        let mated = run("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1", 2);
+        assert_eq!((mated.best, mated.score), (None, -MATE));
+        let stalemate = run("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", 2);
+        assert_eq!((stalemate.best, stalemate.score), (None, 0));
+    }
+
+    #[test]
+    fn wins_hanging_queen() {
+        let report = run("4k3/8/8/3q4/8/8/3R4/3K4 w - - 0 1", 2);
+        assert_eq!(utils::move_string(report.best.unwrap()), "d2d5");
+        assert_eq!(report.depth, 2);
+    }
+
+    #[test]
+    fn scores_draws_by_rule() {
+        // up a queen, but every move hits the fifty-move rule
+        let report = run("7k/8/8/8/8/8/8/KQ6 w - - 99 80", 3);
+        assert_eq!(report.score, 0);
+        let report = run("7k/8/8/8/8/8/8/KQ6 w - - 0 80", 3);
+        assert!(report.score > 500);
+
+        // taking the rook leaves bare kings
+        let report = run("7k/8/8/8/8/8/6r1/6BK w - - 0 1", 2);
+        assert_eq!(utils::move_string(report.best.unwrap()), "h1g2");
+        assert_eq!(report.score, 0);
+    }
+
+    #[test]
+    fn scores_repetition_as_draw() {
+        let limits = Limits {
+            depth: Some(3),
+            ..Default::default()
+        };
+        let run = |game: &Game| search(game, &limits, &AtomicBool::new(false), |_| {});
+
+        // down a queen, but Rg1 repeats a position of the game
+        let fen = "4k3/8/8/8/8/8/q7/4K2R w - - 0 1";
+        let mut game = Game::new(Position::new(fen));
+        assert!(run(&game).score < -300);
+        for s in ["h1g1", "e8d8", "g1h1", "d8e8"] {
+            let mv = game.position().parse_uci_move(s).unwrap();
+            game.play(mv);
+        }
+        let report = run(&game);
+        assert_eq!(utils::move_string(report.best.unwrap()), "h1g1");
+        assert_eq!(report.score, 0);
+    }
+}
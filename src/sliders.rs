@@ -0,0 +1,212 @@
+use crate::tables::SliderAttacks;
+use crate::types::{Bitboard, Square};
+use crate::{magic, masks};
+
+// Slider attack backends computing attacks along the line masks rather than
+// looking them up per square. They need little or no memory, at some cost in
+// speed, for builds that cannot spare the magic tables.
+
+fn diag(ms: &masks::Lookup, sq: Square) -> Bitboard {
+    ms.diag[(7 + sq.rank() - sq.file()) as usize]
+}
+
+fn adiag(ms: &masks::Lookup, sq: Square) -> Bitboard {
+    ms.adiag[(sq.rank() + sq.file()) as usize]
+}
+
+fn rank(ms: &masks::Lookup, sq: Square) -> Bitboard {
+    ms.rank[sq.rank() as usize]
+}
+
+fn file(ms: &masks::Lookup, sq: Square) -> Bitboard {
+    ms.file[sq.file() as usize]
+}
+
+// Hyperbola quintessence finds the attacks towards higher squares with
+// o ^ (o - 2r) and those towards lower squares the same way on the reversed
+// board. Flipping the ranks reverses diagonals and files, ranks need every bit
+// reversed.
+// See: https://www.chessprogramming.org/Hyperbola_Quintessence
+pub struct Hyperbola;
+
+impl Hyperbola {
+    pub fn new(_ms: &masks::Lookup) -> Hyperbola {
+        Hyperbola
+    }
+}
+
+fn hyperbola(occ: Bitboard, sq: Square, line: Bitboard, rev: fn(u64) -> u64) -> Bitboard {
+    let r = sq.bb().0;
+    let line = line.0 & !r;
+    let o = occ.0 & line;
+    let forward = o.wrapping_sub(r.wrapping_mul(2));
+    let reverse = rev(rev(o).wrapping_sub(rev(r).wrapping_mul(2)));
+    Bitboard((forward ^ reverse) & line)
+}
+
+impl SliderAttacks for Hyperbola {
+    fn bishop_attacks(&self, ms: &masks::Lookup, sq: Square, occ: Bitboard) -> Bitboard {
+        hyperbola(occ, sq, diag(ms, sq), u64::swap_bytes)
+            | hyperbola(occ, sq, adiag(ms, sq), u64::swap_bytes)
+    }
+
+    fn rook_attacks(&self, ms: &masks::Lookup, sq: Square, occ: Bitboard) -> Bitboard {
+        hyperbola(occ, sq, file(ms, sq), u64::swap_bytes)
+            | hyperbola(occ, sq, rank(ms, sq), u64::reverse_bits)
+    }
+
+    fn memory(&self) -> usize {
+        0
+    }
+}
+
+// The classical approach cuts each line into the rays either side of the
+// square, and stops each ray at its nearest blocker, found with a bitscan.
+// See: https://www.chessprogramming.org/Classical_Approach
+pub struct Classical;
+
+impl Classical {
+    pub fn new(_ms: &masks::Lookup) -> Classical {
+        Classical
+    }
+}
+
+fn classical(occ: Bitboard, sq: Square, line: Bitboard) -> Bitboard {
+    let (occ, line) = (occ.0, line.0);
+    let up = line & (!1u64 << sq.index());
+    let down = line & ((1u64 << sq.index()) - 1);
+
+    // towards higher squares, up to and including the lowest blocker
+    let mut ret = up;
+    let blockers = up & occ;
+    if blockers != 0 {
+        ret &= (blockers & blockers.wrapping_neg())
+            .wrapping_mul(2)
+            .wrapping_sub(1);
+    }
+
+    // towards lower squares, down to and including the highest blocker
+    let blockers = down & occ;
+    if blockers != 0 {
+        ret |= down & !((1u64 << (63 - blockers.leading_zeros())) - 1);
+    } else {
+        ret |= down;
+    }
+    Bitboard(ret)
+}
+
+impl SliderAttacks for Classical {
+    fn bishop_attacks(&self, ms: &masks::Lookup, sq: Square, occ: Bitboard) -> Bitboard {
+        classical(occ, sq, diag(ms, sq)) | classical(occ, sq, adiag(ms, sq))
+    }
+
+    fn rook_attacks(&self, ms: &masks::Lookup, sq: Square, occ: Bitboard) -> Bitboard {
+        classical(occ, sq, file(ms, sq)) | classical(occ, sq, rank(ms, sq))
+    }
+
+    fn memory(&self) -> usize {
+        0
+    }
+}
+
+// Kindergarten bitboards gather the six inner squares of a line into an index
+// with a multiplication, and look the attacks up in small per-file and per-rank
+// tables. Ranks and diagonals share the file-indexed table, filled on all ranks
+// so the line mask picks out the right one.
+// See: https://www.chessprogramming.org/Kindergarten_Bitboards
+pub struct Kindergarten {
+    // attacks from each file of the first rank, copied to all ranks
+    pub fill_up: [[Bitboard; 64]; 8],
+    // attacks from each rank of the a-file
+    pub a_file: [[Bitboard; 64]; 8],
+}
+
+const B_FILE: u64 = 0x0202020202020202;
+const C2_H7: u64 = 0x0004081020408000;
+
+impl Kindergarten {
+    pub fn new(ms: &masks::Lookup) -> Kindergarten {
+        let mut ret = Kindergarten {
+            fill_up: [[Bitboard::EMPTY; 64]; 8],
+            a_file: [[Bitboard::EMPTY; 64]; 8],
+        };
+        // inner squares of the first rank and of the a-file
+        let (inner_rank, inner_file) = (Bitboard(0x7e), Bitboard(0x0001010101010100));
+        for i in 0..8 {
+            for occ in inner_rank.subsets() {
+                let idx = (occ.0.wrapping_mul(B_FILE) >> 58) as usize;
+                let atk = magic::ratk(Square::from_coords(0, i as u8), occ) & ms.rank[0];
+                ret.fill_up[i][idx] = Bitboard(atk.0 * 0x0101010101010101);
+            }
+            for occ in inner_file.subsets() {
+                let idx = (occ.0.wrapping_mul(C2_H7) >> 58) as usize;
+                ret.a_file[i][idx] = magic::ratk(Square::from_coords(i as u8, 0), occ) & ms.file[0];
+            }
+        }
+        ret
+    }
+
+    fn line(&self, occ: Bitboard, sq: Square, line: Bitboard) -> Bitboard {
+        let idx = ((occ & line).0.wrapping_mul(B_FILE) >> 58) as usize;
+        self.fill_up[sq.file() as usize][idx] & line
+    }
+}
+
+impl SliderAttacks for Kindergarten {
+    fn bishop_attacks(&self, ms: &masks::Lookup, sq: Square, occ: Bitboard) -> Bitboard {
+        self.line(occ, sq, diag(ms, sq)) | self.line(occ, sq, adiag(ms, sq))
+    }
+
+    fn rook_attacks(&self, ms: &masks::Lookup, sq: Square, occ: Bitboard) -> Bitboard {
+        let fl = sq.file() as u32;
+        let idx = (((occ >> fl) & ms.file[0]).0.wrapping_mul(C2_H7) >> 58) as usize;
+        self.line(occ, sq, rank(ms, sq)) | (self.a_file[sq.rank() as usize][idx] << fl)
+    }
+
+    fn memory(&self) -> usize {
+        std::mem::size_of::<Kindergarten>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // walks every occupancy of the lines through each square, with the rest of
+    // the board filled at random since it must not matter
+    fn check_all_occupancies(ms: &masks::Lookup, t: &impl SliderAttacks) {
+        let mut rng = magic::Prng::new(7);
+        for sq in Square::all() {
+            let (bishop, rook) = (
+                magic::batk(sq, Bitboard::EMPTY),
+                magic::ratk(sq, Bitboard::EMPTY),
+            );
+            for occ in (bishop | sq.bb()).subsets() {
+                let occ = occ | (Bitboard(rng.next()) & !bishop);
+                assert_eq!(t.bishop_attacks(ms, sq, occ), magic::batk(sq, occ));
+            }
+            for occ in (rook | sq.bb()).subsets() {
+                let occ = occ | (Bitboard(rng.next()) & !rook);
+                assert_eq!(t.rook_attacks(ms, sq, occ), magic::ratk(sq, occ));
+            }
+        }
+    }
+
+    #[test]
+    fn hyperbola_matches_reference_attacks() {
+        let ms = masks::Lookup::new();
+        check_all_occupancies(&ms, &Hyperbola::new(&ms));
+    }
+
+    #[test]
+    fn classical_matches_reference_attacks() {
+        let ms = masks::Lookup::new();
+        check_all_occupancies(&ms, &Classical::new(&ms));
+    }
+
+    #[test]
+    fn kindergarten_matches_reference_attacks() {
+        let ms = masks::Lookup::new();
+        check_all_occupancies(&ms, &Kindergarten::new(&ms));
+    }
+}
@@ -0,0 +1,200 @@
+use crate::epd::Epd;
+use crate::game::Game;
+use crate::types::Move;
+use crate::{san, search};
+
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+
+// suite searches each position of an EPD test suite such as WAC or STS and
+// prints whether the move found meets its bm, am and dm operations, then the
+// number solved. Without a limit, a position is searched to its acd depth if
+// it has one and for a second otherwise.
+//
+// ragfish suite FILE [--depth N] [--millis N]
+const USAGE: &str = "usage: ragfish suite FILE [--depth N] [--millis N]";
+
+const DEFAULT_MILLIS: u64 = 1000;
+
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Options {
+    pub path: String,
+    pub depth: Option<u8>,
+    pub millis: Option<u64>,
+}
+
+pub fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut args = args.iter();
+    let mut ret = Options {
+        path: args.next().ok_or("expected a file")?.clone(),
+        ..Default::default()
+    };
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or(format!("{} needs a value", flag))?;
+        let number = || {
+            value
+                .parse::<u64>()
+                .map_err(|_| format!("bad value {} for {}", value, flag))
+        };
+        match flag.as_str() {
+            "--depth" => ret.depth = Some(number()?.clamp(1, u8::MAX as u64) as u8),
+            "--millis" => ret.millis = Some(number()?),
+            _ => return Err(format!("unknown flag {}", flag)),
+        }
+    }
+    Ok(ret)
+}
+
+pub fn run(args: &[String]) {
+    let result = parse_args(args).and_then(|opts| {
+        let text = std::fs::read_to_string(&opts.path).map_err(|e| e.to_string())?;
+        run_suite(&text, &opts);
+        Ok(())
+    });
+    if let Err(e) = result {
+        eprintln!("{}\n{}", e, USAGE);
+        std::process::exit(2);
+    }
+}
+
+fn run_suite(text: &str, opts: &Options) {
+    let (mut solved, mut tests) = (0, 0);
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let name = format!("line {}", i + 1);
+        let epd = match Epd::parse(line) {
+            Ok(epd) => epd,
+            Err(e) => {
+                println!("{}: {}", name, e);
+                continue;
+            }
+        };
+        let name = epd.id.as_deref().unwrap_or(&name);
+
+        let (depth, millis) = match (opts.depth, opts.millis, epd.depth) {
+            (None, None, Some(acd)) => (Some(acd), None),
+            (None, None, None) => (None, Some(DEFAULT_MILLIS)),
+            (depth, millis, _) => (depth, millis),
+        };
+        let limits = search::Limits {
+            depth,
+            nodes: None,
+            time: millis.map(Duration::from_millis),
+        };
+        let report = search::search(
+            &Game::new(epd.position),
+            &limits,
+            &AtomicBool::new(false),
+            |_| {},
+        );
+        let Some(best) = report.best else {
+            println!("{}: no legal moves", name);
+            continue;
+        };
+        let Some(ok) = solves(&epd, best, report.score) else {
+            println!("{}: nothing to solve", name);
+            continue;
+        };
+
+        tests += 1;
+        solved += ok as usize;
+        println!(
+            "{} {} {} {} (expected {})",
+            name,
+            if ok { "solved" } else { "unsolved" },
+            san::format(&epd.position, best),
+            search::score_string(report.score),
+            goal(&epd)
+        );
+    }
+    println!("solved {} of {}", solved, tests);
+}
+
+// solves tells whether playing best with the given score meets the bm, am and
+// dm operations of epd, or None if it has none of them
+fn solves(epd: &Epd, best: Move, score: i32) -> Option<bool> {
+    if epd.best_moves.is_empty() && epd.avoid_moves.is_empty() && epd.mate.is_none() {
+        return None;
+    }
+    let best_ok = epd.best_moves.is_empty() || epd.best_moves.contains(&best);
+    let avoid_ok = !epd.avoid_moves.contains(&best);
+    let mate_ok = epd
+        .mate
+        .is_none_or(|moves| search::mate_in(score).is_some_and(|n| n > 0 && n as u32 <= moves));
+    Some(best_ok && avoid_ok && mate_ok)
+}
+
+// goal writes the bm, am and dm operations of epd
+fn goal(epd: &Epd) -> String {
+    let moves = |moves: &[Move]| -> Vec<String> {
+        moves
+            .iter()
+            .map(|&mv| san::format(&epd.position, mv))
+            .collect()
+    };
+    let mut ret = Vec::new();
+    if !epd.best_moves.is_empty() {
+        ret.push(format!("bm {}", moves(&epd.best_moves).join(" ")));
+    }
+    if !epd.avoid_moves.is_empty() {
+        ret.push(format!("am {}", moves(&epd.avoid_moves).join(" ")));
+    }
+    if let Some(mate) = epd.mate {
+        ret.push(format!("dm {}", mate));
+    }
+    ret.join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::args;
+
+    #[test]
+    fn parses_flags() {
+        assert_eq!(
+            parse_args(&args("wac.epd --depth 6 --millis 500")),
+            Ok(Options {
+                path: String::from("wac.epd"),
+                depth: Some(6),
+                millis: Some(500),
+            })
+        );
+        assert_eq!(parse_args(&args("sts.epd")).unwrap().depth, None);
+        assert!(parse_args(&args("")).is_err());
+        assert!(parse_args(&args("wac.epd --depth")).is_err());
+        assert!(parse_args(&args("wac.epd --nodes 5")).is_err());
+    }
+
+    #[test]
+    fn judges_moves() {
+        let run = |line: &str| {
+            let epd = Epd::parse(line).unwrap();
+            let limits = search::Limits {
+                depth: Some(4),
+                ..Default::default()
+            };
+            let report = search::search(
+                &Game::new(epd.position),
+                &limits,
+                &AtomicBool::new(false),
+                |_| {},
+            );
+            solves(&epd, report.best.unwrap(), report.score)
+        };
+
+        let mate_in_two = "7k/8/5K2/8/8/8/8/R7 w - -";
+        assert_eq!(run(&format!("{} dm 2;", mate_in_two)), Some(true));
+        assert_eq!(run(&format!("{} dm 1;", mate_in_two)), Some(false));
+        let queen = "4k3/8/8/3q4/8/8/3R4/3K4 w - -";
+        assert_eq!(
+            run(&format!("{} bm Rxd5; id \"queen\";", queen)),
+            Some(true)
+        );
+        assert_eq!(run(&format!("{} am Rxd5;", queen)), Some(false));
+        assert_eq!(run(&format!("{} bm Kc1 Ke1;", queen)), Some(false));
+        assert_eq!(run(&format!("{} id \"none\";", queen)), None);
+    }
+}
@@ -1,33 +1,251 @@
-use crate::aliases::Bitboard;
-use crate::{magic, masks};
+use crate::types::{Bitboard, Square};
+use crate::{magic, magic_numbers, masks};
 
-pub struct Lookup {
+// Attack lookups for sliding pieces, given the occupancy of the board
+pub trait SliderAttacks {
+    fn bishop_attacks(&self, ms: &masks::Lookup, sq: Square, occ: Bitboard) -> Bitboard;
+    fn rook_attacks(&self, ms: &masks::Lookup, sq: Square, occ: Bitboard) -> Bitboard;
+
+    // memory returns the size of the tables in bytes
+    fn memory(&self) -> usize;
+}
+
+// The backend used for move generation, picked with the pext or hyperbola
+// feature, pext winning when both are on. The pext backend falls back to magics
+// on CPUs without BMI2.
+#[cfg(not(any(feature = "pext", feature = "hyperbola")))]
+pub type Lookup = Magics;
+#[cfg(feature = "pext")]
+pub type Lookup = crate::pext::Backend;
+#[cfg(all(feature = "hyperbola", not(feature = "pext")))]
+pub type Lookup = crate::sliders::Hyperbola;
+
+// Sliding piece attacks live in one contiguous table, with each square's
+// magic indexing from its own offset ("fancy" magic bitboards).
+// See: https://www.chessprogramming.org/Magic_Bitboards#Fancy
+pub struct Magics {
     pub bmag: [magic::Magic; 64],
-    pub bmag_tbl: [Vec<Bitboard>; 64],
+    pub boff: [usize; 64],
     pub rmag: [magic::Magic; 64],
-    pub rmag_tbl: [Vec<Bitboard>; 64],
+    pub roff: [usize; 64],
+    pub attacks: Vec<Bitboard>,
+}
+
+// How the per-square tables are placed in the shared table
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Layout {
+    // one after another
+    Dense,
+    // each overlapping the end of the ones before as far as it fits, so runs
+    // of unused slots at the ends of tables are filled and slots holding the
+    // same attack set shared. Plain magics use their tables up to the ends,
+    // so this mostly pays off with black magics.
+    Packed,
 }
 
-impl Lookup {
-    pub fn new(ms: &masks::Lookup) -> Lookup {
-        // Duration allocated to generate each magic
-        let duration = std::time::Duration::from_millis(0);
-        let mut ret: Lookup = Lookup {
-            bmag: [Default::default(); 64],
-            bmag_tbl: [(); 64].map(|_| Default::default()),
-            rmag: [Default::default(); 64],
-            rmag_tbl: [(); 64].map(|_| Default::default()),
+impl Magics {
+    // new builds the attack tables from the magics shipped in magic_numbers
+    pub fn new(ms: &masks::Lookup) -> Magics {
+        Magics::from_magics(
+            ms,
+            &magic_numbers::BISHOP,
+            &magic_numbers::ROOK,
+            Layout::Packed,
+        )
+        .expect("shipped magics are invalid")
+    }
+
+    // from_magics builds the attack tables from the given magics, failing with
+    // the offending square if one of them is invalid
+    pub fn from_magics(
+        ms: &masks::Lookup,
+        bmag: &[magic::Magic; 64],
+        rmag: &[magic::Magic; 64],
+        layout: Layout,
+    ) -> Result<Magics, String> {
+        let mut btbl: [Vec<Bitboard>; 64] = std::array::from_fn(|_| Vec::new());
+        let mut rtbl: [Vec<Bitboard>; 64] = std::array::from_fn(|_| Vec::new());
+        for sq in Square::all() {
+            btbl[sq.index()] = magic::fill_mag(ms, &bmag[sq.index()], sq, true)
+                .ok_or(format!("invalid bishop magic on {}", sq))?;
+        }
+        for sq in Square::all() {
+            rtbl[sq.index()] = magic::fill_mag(ms, &rmag[sq.index()], sq, false)
+                .ok_or(format!("invalid rook magic on {}", sq))?;
+        }
+        Ok(Magics::pack(bmag, &btbl, rmag, &rtbl, layout))
+    }
+
+    // load builds the attack tables from magics saved by magic-search
+    pub fn load(ms: &masks::Lookup, path: &std::path::Path) -> Result<Magics, String> {
+        let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+        let (bmag, rmag) =
+            magic::from_bytes(&bytes).ok_or(format!("{} is not a magics file", path.display()))?;
+        Magics::from_magics(ms, &bmag, &rmag, Layout::Packed)
+    }
+
+    // generate searches for new magics, spending the given budget on each
+    // square. This is slow, the magic-search command is the way to replace
+    // magic_numbers.
+    pub fn generate(ms: &masks::Lookup, budget: magic::Budget, seed: u64) -> Magics {
+        let mut rng = magic::Prng::new(seed);
+        let mut bmag = [magic::Magic::default(); 64];
+        let mut btbl: [Vec<Bitboard>; 64] = std::array::from_fn(|_| Vec::new());
+        let mut rmag = [magic::Magic::default(); 64];
+        let mut rtbl: [Vec<Bitboard>; 64] = std::array::from_fn(|_| Vec::new());
+        for sq in Square::all() {
+            (bmag[sq.index()], btbl[sq.index()]) = magic::find_bmag(ms, sq, budget, &mut rng);
+        }
+        for sq in Square::all() {
+            (rmag[sq.index()], rtbl[sq.index()]) = magic::find_rmag(ms, sq, budget, &mut rng);
+        }
+        Magics::pack(&bmag, &btbl, &rmag, &rtbl, Layout::Packed)
+    }
+
+    // pack lays the per-square tables out in one table. Empty slots hold the
+    // empty set, which is never a slider attack set.
+    fn pack(
+        bmag: &[magic::Magic; 64],
+        btbl: &[Vec<Bitboard>; 64],
+        rmag: &[magic::Magic; 64],
+        rtbl: &[Vec<Bitboard>; 64],
+        layout: Layout,
+    ) -> Magics {
+        let mut ret = Magics {
+            bmag: *bmag,
+            boff: [0; 64],
+            rmag: *rmag,
+            roff: [0; 64],
+            attacks: Vec::new(),
         };
-        for sq in 0..64 {
-            let (mag, tbl) = magic::find_bmag(ms, sq, duration);
-            ret.bmag[sq as usize] = mag;
-            ret.bmag_tbl[sq as usize] = tbl;
+
+        // placing the largest tables first leaves the smaller ones to fill gaps
+        let mut order: Vec<(bool, usize)> =
+            (0..64).flat_map(|sq| [(true, sq), (false, sq)]).collect();
+        if layout == Layout::Packed {
+            order.sort_by_key(|&(bishop, sq)| {
+                std::cmp::Reverse(if bishop {
+                    btbl[sq].len()
+                } else {
+                    rtbl[sq].len()
+                })
+            });
         }
-        for sq in 0..64 {
-            let (mag, tbl) = magic::find_rmag(ms, sq, duration);
-            ret.rmag[sq as usize] = mag;
-            ret.rmag_tbl[sq as usize] = tbl;
+
+        for (bishop, sq) in order {
+            let tbl = if bishop { &btbl[sq] } else { &rtbl[sq] };
+            let end = ret.attacks.len();
+            let fits = |off: &usize| {
+                tbl.iter()
+                    .zip(ret.attacks[*off..].iter())
+                    .all(|(&a, &b)| a.is_empty() || b.is_empty() || a == b)
+            };
+            let off = match layout {
+                Layout::Dense => end,
+                // only trying the end of the table keeps the search short
+                Layout::Packed => (end.saturating_sub(tbl.len())..end)
+                    .find(fits)
+                    .unwrap_or(end),
+            };
+            if end < off + tbl.len() {
+                ret.attacks.resize(off + tbl.len(), Bitboard::EMPTY);
+            }
+            for (slot, &a) in ret.attacks[off..].iter_mut().zip(tbl.iter()) {
+                if !a.is_empty() {
+                    *slot = a;
+                }
+            }
+            if bishop {
+                ret.boff[sq] = off;
+            } else {
+                ret.roff[sq] = off;
+            }
         }
         ret
     }
 }
+
+impl SliderAttacks for Magics {
+    fn bishop_attacks(&self, ms: &masks::Lookup, sq: Square, occ: Bitboard) -> Bitboard {
+        let hash = self.bmag[sq.index()].transform(occ & ms.brel[sq.index()]);
+        self.attacks[self.boff[sq.index()] + hash as usize]
+    }
+
+    fn rook_attacks(&self, ms: &masks::Lookup, sq: Square, occ: Bitboard) -> Bitboard {
+        let hash = self.rmag[sq.index()].transform(occ & ms.rrel[sq.index()]);
+        self.attacks[self.roff[sq.index()] + hash as usize]
+    }
+
+    fn memory(&self) -> usize {
+        std::mem::size_of::<Magics>() + self.attacks.len() * std::mem::size_of::<Bitboard>()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // walks every subset of each relevance mask, comparing against the ray loops
+    pub(crate) fn check_reference_attacks(ms: &masks::Lookup, t: &impl SliderAttacks) {
+        for sq in Square::all() {
+            for occ in ms.brel[sq.index()].subsets() {
+                assert_eq!(t.bishop_attacks(ms, sq, occ), magic::batk(sq, occ));
+            }
+            for occ in ms.rrel[sq.index()].subsets() {
+                assert_eq!(t.rook_attacks(ms, sq, occ), magic::ratk(sq, occ));
+            }
+        }
+    }
+
+    #[test]
+    fn layouts_match_reference_attacks() {
+        let ms = masks::Lookup::new();
+        for layout in [Layout::Dense, Layout::Packed] {
+            let t = Magics::from_magics(&ms, &magic_numbers::BISHOP, &magic_numbers::ROOK, layout)
+                .unwrap();
+            check_reference_attacks(&ms, &t);
+        }
+    }
+
+    #[test]
+    fn packing_shares_slots() {
+        let ms = masks::Lookup::new();
+        let dense = Magics::from_magics(
+            &ms,
+            &magic_numbers::BISHOP,
+            &magic_numbers::ROOK,
+            Layout::Dense,
+        )
+        .unwrap();
+        let total: usize = magic_numbers::BISHOP
+            .iter()
+            .chain(magic_numbers::ROOK.iter())
+            .map(|m| 1 << (64 - m.shift))
+            .sum();
+        assert_eq!(dense.attacks.len(), total);
+
+        // black magics leave runs of unused slots at the ends of each table,
+        // which packing overlaps with the neighbouring tables
+        let mut rng = magic::Prng::new(1);
+        let mut bmag = [magic::Magic::default(); 64];
+        let mut rmag = [magic::Magic::default(); 64];
+        for sq in Square::all() {
+            for (mags, bishop) in [(&mut bmag, true), (&mut rmag, false)] {
+                (mags[sq.index()], _) = magic::find_mag(
+                    &ms,
+                    sq,
+                    magic::Budget::Tries(0),
+                    bishop,
+                    magic::Variant::Black,
+                    &mut rng,
+                )
+                .unwrap();
+            }
+        }
+        let dense = Magics::from_magics(&ms, &bmag, &rmag, Layout::Dense).unwrap();
+        let packed = Magics::from_magics(&ms, &bmag, &rmag, Layout::Packed).unwrap();
+        assert!(packed.attacks.len() < dense.attacks.len() * 9 / 10);
+        assert!(packed.memory() < dense.memory());
+        check_reference_attacks(&ms, &packed);
+    }
+}
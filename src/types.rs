@@ -0,0 +1,393 @@
+use crate::{enums, utils};
+
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not, Shl, Shr};
+
+// A square of the board, indexed 8 * rank + file from a1 = 0 to h8 = 63
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct Square(u8);
+
+#[rustfmt::skip]
+impl Square {
+    pub const A1: Square = Square(0); pub const B1: Square = Square(1); pub const C1: Square = Square(2); pub const D1: Square = Square(3); pub const E1: Square = Square(4); pub const F1: Square = Square(5); pub const G1: Square = Square(6); pub const H1: Square = Square(7);
+    pub const A2: Square = Square(8); pub const B2: Square = Square(9); pub const C2: Square = Square(10); pub const D2: Square = Square(11); pub const E2: Square = Square(12); pub const F2: Square = Square(13); pub const G2: Square = Square(14); pub const H2: Square = Square(15);
+    pub const A3: Square = Square(16); pub const B3: Square = Square(17); pub const C3: Square = Square(18); pub const D3: Square = Square(19); pub const E3: Square = Square(20); pub const F3: Square = Square(21); pub const G3: Square = Square(22); pub const H3: Square = Square(23);
+    pub const A4: Square = Square(24); pub const B4: Square = Square(25); pub const C4: Square = Square(26); pub const D4: Square = Square(27); pub const E4: Square = Square(28); pub const F4: Square = Square(29); pub const G4: Square = Square(30); pub const H4: Square = Square(31);
+    pub const A5: Square = Square(32); pub const B5: Square = Square(33); pub const C5: Square = Square(34); pub const D5: Square = Square(35); pub const E5: Square = Square(36); pub const F5: Square = Square(37); pub const G5: Square = Square(38); pub const H5: Square = Square(39);
+    pub const A6: Square = Square(40); pub const B6: Square = Square(41); pub const C6: Square = Square(42); pub const D6: Square = Square(43); pub const E6: Square = Square(44); pub const F6: Square = Square(45); pub const G6: Square = Square(46); pub const H6: Square = Square(47);
+    pub const A7: Square = Square(48); pub const B7: Square = Square(49); pub const C7: Square = Square(50); pub const D7: Square = Square(51); pub const E7: Square = Square(52); pub const F7: Square = Square(53); pub const G7: Square = Square(54); pub const H7: Square = Square(55);
+    pub const A8: Square = Square(56); pub const B8: Square = Square(57); pub const C8: Square = Square(58); pub const D8: Square = Square(59); pub const E8: Square = Square(60); pub const F8: Square = Square(61); pub const G8: Square = Square(62); pub const H8: Square = Square(63);
+}
+
+impl Square {
+    // new returns the square with the given index, which must be below 64
+    pub const fn new(index: u8) -> Square {
+        assert!(index < 64, "square index out of range");
+        Square(index)
+    }
+
+    pub const fn from_coords(rank: u8, file: u8) -> Square {
+        Square::new(8 * rank + file)
+    }
+
+    // all iterates over the squares from a1 to h8
+    pub fn all() -> impl Iterator<Item = Square> {
+        (0..64).map(Square)
+    }
+
+    pub const fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub const fn rank(self) -> u8 {
+        self.0 / 8
+    }
+
+    pub const fn file(self) -> u8 {
+        self.0 % 8
+    }
+
+    pub const fn bb(self) -> Bitboard {
+        Bitboard(1 << self.0)
+    }
+
+    // offset moves delta squares along the index, e.g. 8 for one rank up. The
+    // result must stay on the board, which is only checked in debug builds as
+    // move generation leans on this.
+    pub const fn offset(self, delta: i8) -> Square {
+        let index = (self.0 as i8 + delta) as u8;
+        debug_assert!(index < 64, "square offset off the board");
+        Square(index)
+    }
+
+    // try_offset moves by whole ranks and files, or returns None when that
+    // leaves the board
+    pub fn try_offset(self, ranks: i8, files: i8) -> Option<Square> {
+        let rank = self.rank() as i8 + ranks;
+        let file = self.file() as i8 + files;
+        if (0..8).contains(&rank) && (0..8).contains(&file) {
+            Some(Square::from_coords(rank as u8, file as u8))
+        } else {
+            None
+        }
+    }
+
+    // flip mirrors the square top to bottom, e.g. a1 to a8
+    pub const fn flip(self) -> Square {
+        Square(self.0 ^ 56)
+    }
+}
+
+impl std::fmt::Display for Square {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            (b'a' + self.file()) as char,
+            (b'1' + self.rank()) as char
+        )
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ParseSquareError;
+
+impl std::fmt::Display for ParseSquareError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "expected a square such as e4")
+    }
+}
+
+impl std::error::Error for ParseSquareError {}
+
+impl std::str::FromStr for Square {
+    type Err = ParseSquareError;
+
+    fn from_str(s: &str) -> Result<Square, ParseSquareError> {
+        match s.as_bytes() {
+            &[fl @ b'a'..=b'h', rk @ b'1'..=b'8'] => Ok(Square::from_coords(rk - b'1', fl - b'a')),
+            _ => Err(ParseSquareError),
+        }
+    }
+}
+
+// A set of squares, one bit per square index
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Bitboard(pub u64);
+
+impl Bitboard {
+    pub const EMPTY: Bitboard = Bitboard(0);
+    pub const FULL: Bitboard = Bitboard(!0);
+
+    pub const fn contains(self, sq: Square) -> bool {
+        self.0 & (1 << sq.0) != 0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub const fn count(self) -> u32 {
+        self.0.count_ones()
+    }
+
+    // first returns the lowest square in the set
+    pub const fn first(self) -> Option<Square> {
+        if self.0 == 0 {
+            None
+        } else {
+            Some(Square(self.0.trailing_zeros() as u8))
+        }
+    }
+
+    // squares iterates over the squares in the set, from a1 to h8
+    pub const fn squares(self) -> Squares {
+        Squares(self)
+    }
+
+    // subsets iterates over every subset of the set, starting with the empty set
+    // See: https://www.chessprogramming.org/Traversing_Subsets_of_a_Set
+    pub const fn subsets(self) -> Subsets {
+        Subsets {
+            set: self.0,
+            next: Some(0),
+        }
+    }
+}
+
+impl From<Square> for Bitboard {
+    fn from(sq: Square) -> Bitboard {
+        sq.bb()
+    }
+}
+
+impl std::fmt::Display for Bitboard {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", utils::bb_string(*self))
+    }
+}
+
+impl BitAnd for Bitboard {
+    type Output = Bitboard;
+
+    fn bitand(self, rhs: Bitboard) -> Bitboard {
+        Bitboard(self.0 & rhs.0)
+    }
+}
+
+impl BitOr for Bitboard {
+    type Output = Bitboard;
+
+    fn bitor(self, rhs: Bitboard) -> Bitboard {
+        Bitboard(self.0 | rhs.0)
+    }
+}
+
+impl BitXor for Bitboard {
+    type Output = Bitboard;
+
+    fn bitxor(self, rhs: Bitboard) -> Bitboard {
+        Bitboard(self.0 ^ rhs.0)
+    }
+}
+
+impl Not for Bitboard {
+    type Output = Bitboard;
+
+    fn not(self) -> Bitboard {
+        Bitboard(!self.0)
+    }
+}
+
+impl BitAndAssign for Bitboard {
+    fn bitand_assign(&mut self, rhs: Bitboard) {
+        self.0 &= rhs.0;
+    }
+}
+
+impl BitOrAssign for Bitboard {
+    fn bitor_assign(&mut self, rhs: Bitboard) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl BitXorAssign for Bitboard {
+    fn bitxor_assign(&mut self, rhs: Bitboard) {
+        self.0 ^= rhs.0;
+    }
+}
+
+impl Shl<u32> for Bitboard {
+    type Output = Bitboard;
+
+    fn shl(self, rhs: u32) -> Bitboard {
+        Bitboard(self.0 << rhs)
+    }
+}
+
+impl Shr<u32> for Bitboard {
+    type Output = Bitboard;
+
+    fn shr(self, rhs: u32) -> Bitboard {
+        Bitboard(self.0 >> rhs)
+    }
+}
+
+impl IntoIterator for Bitboard {
+    type Item = Square;
+    type IntoIter = Squares;
+
+    fn into_iter(self) -> Squares {
+        self.squares()
+    }
+}
+
+impl FromIterator<Square> for Bitboard {
+    fn from_iter<I: IntoIterator<Item = Square>>(iter: I) -> Bitboard {
+        iter.into_iter()
+            .fold(Bitboard::EMPTY, |acc, sq| acc | sq.bb())
+    }
+}
+
+pub struct Squares(Bitboard);
+
+impl Iterator for Squares {
+    type Item = Square;
+
+    fn next(&mut self) -> Option<Square> {
+        let sq = self.0.first()?;
+        self.0 .0 &= self.0 .0 - 1;
+        Some(sq)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.0.count() as usize;
+        (n, Some(n))
+    }
+}
+
+impl ExactSizeIterator for Squares {}
+
+pub struct Subsets {
+    set: u64,
+    next: Option<u64>,
+}
+
+impl Iterator for Subsets {
+    type Item = Bitboard;
+
+    fn next(&mut self) -> Option<Bitboard> {
+        let ret = self.next?;
+        let next = ret.wrapping_sub(self.set) & self.set;
+        self.next = if next == 0 { None } else { Some(next) };
+        Some(Bitboard(ret))
+    }
+}
+
+// Using From-To based move encoding
+//
+//      0    |   0   |    0    |    0    | 000000 | 000000
+//  ---------|-------|---------|---------|--------|--------
+//  promotion|capture|special 1|special 0|  from  |   to
+//
+// https://www.chessprogramming.org/Encoding_Moves
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Move(u16);
+
+pub const FLAG_QUIET_MOVE: u8 = 0;
+pub const FLAG_DOUBLE_PAWN_PUSH: u8 = 1;
+pub const FLAG_KING_CASTLE: u8 = 2;
+pub const FLAG_QUEEN_CASTLE: u8 = 3;
+pub const FLAG_CAPTURE: u8 = 4;
+pub const FLAG_EP_CAPTURE: u8 = 5;
+pub const FLAG_PROMOTE_KNIGHT: u8 = 8;
+pub const FLAG_PROMOTE_BISHOP: u8 = 9;
+pub const FLAG_PROMOTE_ROOK: u8 = 10;
+pub const FLAG_PROMOTE_QUEEN: u8 = 11;
+pub const FLAG_CAPTURE_PROMOTE_KNIGHT: u8 = 12;
+pub const FLAG_CAPTURE_PROMOTE_BISHOP: u8 = 13;
+pub const FLAG_CAPTURE_PROMOTE_ROOK: u8 = 14;
+pub const FLAG_CAPTURE_PROMOTE_QUEEN: u8 = 15;
+
+impl Move {
+    pub const fn new(from: Square, to: Square, flag: u8) -> Move {
+        Move(to.0 as u16 | (from.0 as u16) << 6 | (flag as u16) << 12)
+    }
+
+    pub const fn from(self) -> Square {
+        Square(((self.0 >> 6) & 0x3f) as u8)
+    }
+
+    pub const fn to(self) -> Square {
+        Square((self.0 & 0x3f) as u8)
+    }
+
+    pub const fn flag(self) -> u8 {
+        ((self.0 >> 12) & 0xf) as u8
+    }
+
+    pub const fn is_capture(self) -> bool {
+        self.flag() & FLAG_CAPTURE != 0
+    }
+
+    pub const fn is_castle(self) -> bool {
+        matches!(self.flag(), FLAG_KING_CASTLE | FLAG_QUEEN_CASTLE)
+    }
+
+    // promotion returns the piece a pawn promotes to, if this is a promotion
+    pub const fn promotion(self) -> Option<enums::Piece> {
+        match self.flag() {
+            FLAG_PROMOTE_KNIGHT | FLAG_CAPTURE_PROMOTE_KNIGHT => Some(enums::Piece::Knight),
+            FLAG_PROMOTE_BISHOP | FLAG_CAPTURE_PROMOTE_BISHOP => Some(enums::Piece::Bishop),
+            FLAG_PROMOTE_ROOK | FLAG_CAPTURE_PROMOTE_ROOK => Some(enums::Piece::Rook),
+            FLAG_PROMOTE_QUEEN | FLAG_CAPTURE_PROMOTE_QUEEN => Some(enums::Piece::Queen),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn squares() {
+        assert_eq!((Square::E4.rank(), Square::E4.file()), (3, 4));
+        assert_eq!(Square::E4.to_string(), "e4");
+        assert_eq!("h8".parse(), Ok(Square::H8));
+        assert_eq!("i1".parse::<Square>(), Err(ParseSquareError));
+        assert_eq!("-".parse::<Square>(), Err(ParseSquareError));
+        assert_eq!(Square::E2.offset(16), Square::E4);
+        assert_eq!(Square::B1.try_offset(2, -1), Some(Square::A3));
+        assert_eq!(Square::A1.try_offset(0, -1), None);
+        assert_eq!(Square::C2.flip(), Square::C7);
+        assert_eq!(Square::all().count(), 64);
+    }
+
+    #[test]
+    fn bitboards() {
+        let bb: Bitboard = [Square::A1, Square::D4, Square::H8].into_iter().collect();
+        assert_eq!(bb.count(), 3);
+        assert!(bb.contains(Square::D4) && !bb.contains(Square::D5));
+        assert_eq!(bb.first(), Some(Square::A1));
+        assert_eq!(
+            bb.squares().collect::<Vec<_>>(),
+            vec![Square::A1, Square::D4, Square::H8]
+        );
+        assert_eq!((bb << 8) & Square::D5.bb(), Square::D5.bb());
+        assert_eq!(Bitboard::EMPTY.first(), None);
+        assert_eq!(bb.subsets().count(), 8);
+        assert!(bb.subsets().all(|s| s & !bb == Bitboard::EMPTY));
+        assert!(bb.to_string().starts_with("0x8000000008000001\n"));
+    }
+
+    #[test]
+    fn moves() {
+        let mv = Move::new(Square::B7, Square::A8, FLAG_CAPTURE_PROMOTE_KNIGHT);
+        assert_eq!((mv.from(), mv.to()), (Square::B7, Square::A8));
+        assert!(mv.is_capture() && !mv.is_castle());
+        assert_eq!(mv.promotion(), Some(enums::Piece::Knight));
+        let mv = Move::new(Square::E1, Square::G1, FLAG_KING_CASTLE);
+        assert!(mv.is_castle() && !mv.is_capture());
+        assert_eq!(mv.promotion(), None);
+    }
+}
@@ -0,0 +1,263 @@
+use crate::game::Game;
+use crate::positions::Position;
+use crate::{enums, search, utils};
+
+use std::io::BufRead;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+// UCI front end
+// See: https://www.wbec-ridderkerk.nl/html/UCIProtocol.html
+
+pub const STARTPOS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+// Limits given with go, times are in milliseconds
+#[derive(Default, Debug, Eq, PartialEq)]
+pub struct Go {
+    pub depth: Option<u8>,
+    pub movetime: Option<u64>,
+    pub wtime: Option<u64>,
+    pub btime: Option<u64>,
+    pub winc: Option<u64>,
+    pub binc: Option<u64>,
+    pub movestogo: Option<u32>,
+    pub nodes: Option<u64>,
+    pub infinite: bool,
+}
+
+// run reads commands from stdin until quit or end of input. Searches run on a
+// separate thread so stop can be read while they are in progress.
+pub fn run() {
+    let stop = AtomicBool::new(false);
+    let mut game = Game::new(Position::new(STARTPOS));
+
+    thread::scope(|s| {
+        let mut search: Option<thread::ScopedJoinHandle<()>> = None;
+        for line in std::io::stdin().lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            let mut tokens = line.split_whitespace();
+            match tokens.next() {
+                Some("uci") => {
+                    println!("id name ragfish {}", env!("CARGO_PKG_VERSION"));
+                    println!("id author the ragfish developers");
+                    println!("uciok");
+                }
+                Some("isready") => println!("readyok"),
+                Some("setoption") => set_option(tokens),
+                Some("ucinewgame") => {
+                    finish(&mut search, &stop);
+                    game = Game::new(Position::new(STARTPOS));
+                }
+                Some("position") => {
+                    finish(&mut search, &stop);
+                    match parse_position(tokens) {
+                        Ok(g) => game = g,
+                        Err(e) => println!("info string {}", e),
+                    }
+                }
+                Some("go") => {
+                    finish(&mut search, &stop);
+                    let go = parse_go(tokens);
+                    stop.store(false, Ordering::SeqCst);
+                    let (game, stop) = (game.clone(), &stop);
+                    search = Some(s.spawn(move || think(game, go, stop)));
+                }
+                Some("stop") => finish(&mut search, &stop),
+                Some("quit") => break,
+                // unknown commands are ignored, as the protocol asks
+                _ => {}
+            }
+        }
+        finish(&mut search, &stop);
+    });
+}
+
+// finish stops the running search, if any, and waits for its bestmove
+fn finish(search: &mut Option<thread::ScopedJoinHandle<()>>, stop: &AtomicBool) {
+    if let Some(handle) = search.take() {
+        stop.store(true, Ordering::SeqCst);
+        handle.join().expect("search thread panicked");
+    }
+}
+
+// think searches the current position of game within the limits of go,
+// printing info after each iteration and the bestmove at the end
+fn think(game: Game, go: Go, stop: &AtomicBool) {
+    let limits = search::Limits {
+        depth: go.depth,
+        nodes: go.nodes,
+        time: time_budget(&go, game.position().side()),
+    };
+    let report = search::search(&game, &limits, stop, |r| {
+        let pv: Vec<String> = r.pv.iter().map(|&mv| utils::move_string(mv)).collect();
+        let millis = r.elapsed.as_millis() as u64;
+        println!(
+            "info depth {} score {} nodes {} nps {} time {} pv {}",
+            r.depth,
+            search::score_string(r.score),
+            r.nodes,
+            r.nodes * 1000 / millis.max(1),
+            millis,
+            pv.join(" ")
+        );
+    });
+
+    // an infinite search must not finish before being told to stop
+    if go.infinite {
+        while !stop.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+    match report.best {
+        Some(mv) => println!("bestmove {}", utils::move_string(mv)),
+        None => println!("bestmove 0000"),
+    }
+}
+
+// time_budget decides how long to think for. Without movetime, it spreads the
+// remaining time over the moves to go, assuming 30 when not told, and keeps a
+// margin for communication overhead.
+fn time_budget(go: &Go, side: enums::Colour) -> Option<Duration> {
+    if go.infinite {
+        return None;
+    }
+    if let Some(movetime) = go.movetime {
+        return Some(Duration::from_millis(movetime));
+    }
+    let (time, inc) = match side {
+        enums::Colour::White => (go.wtime?, go.winc.unwrap_or(0)),
+        enums::Colour::Black => (go.btime?, go.binc.unwrap_or(0)),
+    };
+    let margin = 50.min(time / 2);
+    let budget = time / go.movestogo.unwrap_or(30).max(1) as u64 + inc * 3 / 4;
+    Some(Duration::from_millis(budget.min(time - margin)))
+}
+
+// set_option handles setoption name <id> [value <x>]. There are no options yet.
+fn set_option<'a>(tokens: impl Iterator<Item = &'a str>) {
+    let name: Vec<&str> = tokens
+        .skip_while(|&tok| tok == "name")
+        .take_while(|&tok| tok != "value")
+        .collect();
+    println!("info string unknown option {}", name.join(" "));
+}
+
+pub fn parse_go<'a>(mut tokens: impl Iterator<Item = &'a str>) -> Go {
+    let mut ret = Go::default();
+    while let Some(token) = tokens.next() {
+        let mut value = || tokens.next().and_then(|v| v.parse::<u64>().ok());
+        match token {
+            "depth" => ret.depth = value().map(|v| v.min(u8::MAX as u64) as u8),
+            "movetime" => ret.movetime = value(),
+            "wtime" => ret.wtime = value(),
+            "btime" => ret.btime = value(),
+            "winc" => ret.winc = value(),
+            "binc" => ret.binc = value(),
+            "movestogo" => ret.movestogo = value().map(|v| v as u32),
+            "nodes" => ret.nodes = value(),
+            "infinite" => ret.infinite = true,
+            _ => {}
+        }
+    }
+    ret
+}
+
+// parse_position handles position (startpos | fen <fen>) [moves <move>...],
+// keeping the moves so that the search knows which positions have occurred
+pub fn parse_position<'a>(mut tokens: impl Iterator<Item = &'a str>) -> Result<Game, String> {
+    let pos = match tokens.next() {
+        Some("startpos") => {
+            if let Some(tok) = tokens.next() {
+                if tok != "moves" {
+                    return Err(format!("expected moves, got {}", tok));
+                }
+            }
+            Position::new(STARTPOS)
+        }
+        Some("fen") => {
+            let fen: Vec<&str> = tokens.by_ref().take_while(|&tok| tok != "moves").collect();
+            Position::from_fen(&fen.join(" ")).map_err(|e| e.to_string())?
+        }
+        _ => return Err(String::from("expected startpos or fen")),
+    };
+
+    let mut game = Game::new(pos);
+    for tok in tokens {
+        let mv = game
+            .position()
+            .parse_uci_move(tok)
+            .map_err(|e| format!("{}: {}", tok, e))?;
+        game.play(mv);
+    }
+    Ok(game)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn go_limits() {
+        let go = parse_go("wtime 300000 btime 290000 winc 2000 binc 2000 movestogo 40".split(' '));
+        assert_eq!(
+            go,
+            Go {
+                wtime: Some(300000),
+                btime: Some(290000),
+                winc: Some(2000),
+                binc: Some(2000),
+                movestogo: Some(40),
+                ..Default::default()
+            }
+        );
+        let go = parse_go("depth 6 nodes 100000 movetime 500".split(' '));
+        assert_eq!(
+            (go.depth, go.nodes, go.movetime),
+            (Some(6), Some(100000), Some(500))
+        );
+        assert!(parse_go("infinite".split(' ')).infinite);
+    }
+
+    #[test]
+    fn time_budgets() {
+        let budget = |cmd: &str, side| time_budget(&parse_go(cmd.split(' ')), side);
+        assert_eq!(
+            budget("movetime 500", enums::Colour::White),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(
+            budget("wtime 60000 btime 30000 movestogo 10", enums::Colour::Black),
+            Some(Duration::from_millis(3000))
+        );
+        assert_eq!(
+            budget("wtime 60 winc 1000", enums::Colour::White),
+            Some(Duration::from_millis(30))
+        );
+        assert_eq!(budget("depth 5", enums::Colour::White), None);
+        assert_eq!(budget("infinite", enums::Colour::White), None);
+    }
+
+    #[test]
+    fn position_commands() {
+        let parse = |cmd: &str| parse_position(cmd.split_whitespace()).map(|g| *g.position());
+
+        assert_eq!(parse("startpos").unwrap().to_fen(), STARTPOS);
+        assert_eq!(
+            parse("startpos moves e2e4 c7c5 g1f3").unwrap().to_fen(),
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+        assert_eq!(
+            parse("fen r3k2r/6P1/8/8/8/8/8/R3K2R w KQkq - 0 1 moves e1g1 e8c8 g7h8q")
+                .unwrap()
+                .to_fen(),
+            "2kr3Q/8/8/8/8/8/8/R4RK1 b - - 0 2"
+        );
+        assert!(parse("startpos moves e2e5").is_err());
+        assert!(parse("fen 8/8/8 w - - 0 1").is_err());
+        assert!(parse("midgame").is_err());
+    }
+}
@@ -1,31 +1,23 @@
-use crate::aliases::{Bitboard, Move, Square};
-use crate::{enums, positions};
-
-pub fn square_string(sq: Square) -> String {
-    if sq == enums::Square::Null as Square {
-        return String::from("-");
-    }
-    let (rk, fl) = (sq / 8, sq % 8);
-    format!("{}{}", (b'a' + fl) as char, (b'1' + rk) as char)
-}
+use crate::enums;
+use crate::types::{Bitboard, Move, Square};
 
+// move_string formats mv in long algebraic notation as used by UCI, e.g. e7e8q
 pub fn move_string(mv: Move) -> String {
-    format!(
-        "{}{}",
-        square_string(positions::move_get_from(mv)),
-        square_string(positions::move_get_to(mv))
-    )
+    let mut ret = format!("{}{}", mv.from(), mv.to());
+    if let Some(piece) = mv.promotion() {
+        ret.push(colour_piece_ascii(enums::Colour::Black, piece));
+    }
+    ret
 }
 
 pub fn bb_string(bb: Bitboard) -> String {
     let mut ret = String::new();
-    ret.push_str(&*format!("{:#018x}\n", bb));
+    ret.push_str(&format!("{:#018x}\n", bb.0));
     for rank in (0u8..8).rev() {
-        ret.push_str(&*format!("{} ", rank + 1));
+        ret.push_str(&format!("{} ", rank + 1));
         for file in 0u8..8 {
-            let sq: u8 = 8 * rank + file;
-            let bit: u64 = bb & (1u64 << sq);
-            ret.push_str(if bit != 0 { "X" } else { "." });
+            let sq = Square::from_coords(rank, file);
+            ret.push_str(if bb.contains(sq) { "X" } else { "." });
             ret.push_str(if file <= 6 { " " } else { "\n" });
         }
     }
@@ -33,16 +25,6 @@ pub fn bb_string(bb: Bitboard) -> String {
     ret
 }
 
-pub fn string_square(s: &str) -> Square {
-    if s == "-" {
-        enums::Square::Null as Square
-    } else {
-        let fl = (s.chars().next().expect("bad string for square provided") as u8) - b'a';
-        let rk = (s.chars().nth(1).expect("bad string for square provided") as u8) - b'1';
-        8 * rk + fl
-    }
-}
-
 pub fn ascii_colour_piece(c: char) -> Option<(enums::Colour, enums::Piece)> {
     let colour = if c.is_uppercase() {
         enums::Colour::White
@@ -59,3 +41,26 @@ pub fn ascii_colour_piece(c: char) -> Option<(enums::Colour, enums::Piece)> {
         _ => None,
     }
 }
+
+pub fn colour_piece_ascii(colour: enums::Colour, piece: enums::Piece) -> char {
+    let c = match piece {
+        enums::Piece::King => 'K',
+        enums::Piece::Queen => 'Q',
+        enums::Piece::Rook => 'R',
+        enums::Piece::Bishop => 'B',
+        enums::Piece::Knight => 'N',
+        enums::Piece::Pawn => 'P',
+    };
+    match colour {
+        enums::Colour::White => c,
+        enums::Colour::Black => c.to_ascii_lowercase(),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    // args splits a command line the way the shell would pass it to a command
+    pub(crate) fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+}
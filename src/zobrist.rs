@@ -0,0 +1,71 @@
+use crate::magic;
+
+// Zobrist keys, see: https://www.chessprogramming.org/Zobrist_Hashing
+// They are generated at compile time from a fixed seed, so hashes are the same
+// across runs and builds.
+pub struct Keys {
+    pub pieces: [[[u64; 64]; 6]; 2],
+    pub side: u64,
+
+    // indexed by the full castling rights mask
+    pub castling: [u64; 16],
+
+    pub ep_file: [u64; 8],
+}
+
+impl Keys {
+    const fn new() -> Keys {
+        let mut rng = magic::Prng::new(0x9e3779b97f4a7c15);
+        let mut keys = Keys {
+            pieces: [[[0; 64]; 6]; 2],
+            side: 0,
+            castling: [0; 16],
+            ep_file: [0; 8],
+        };
+
+        let mut colour = 0;
+        while colour < 2 {
+            let mut piece = 0;
+            while piece < 6 {
+                let mut sq = 0;
+                while sq < 64 {
+                    keys.pieces[colour][piece][sq] = rng.next();
+                    sq += 1;
+                }
+                piece += 1;
+            }
+            colour += 1;
+        }
+
+        keys.side = rng.next();
+
+        // no rights hashes to 0, so the keys of the four single rights combine
+        // to give the key of any set of rights
+        let mut right = 0;
+        while right < 4 {
+            keys.castling[1 << right] = rng.next();
+            right += 1;
+        }
+        let mut rights = 0;
+        while rights < 16 {
+            let mut right = 0;
+            while right < 4 {
+                if rights & (1 << right) != 0 && rights != 1 << right {
+                    keys.castling[rights] ^= keys.castling[1 << right];
+                }
+                right += 1;
+            }
+            rights += 1;
+        }
+
+        let mut file = 0;
+        while file < 8 {
+            keys.ep_file[file] = rng.next();
+            file += 1;
+        }
+
+        keys
+    }
+}
+
+pub static KEYS: Keys = Keys::new();